@@ -10,10 +10,18 @@ pub struct TishArgs {
     #[arg(short = 'c', long)]
     pub command: Option<String>,
 
-    /// Don't load environment
+    /// Don't load environment (neither .tishrc nor .tish_profile)
     #[arg(short = 'n', long = "no-env")]
     pub no_env: bool,
 
+    /// Skip .tishrc, a login shell still loads .tish_profile
+    #[arg(long)]
+    pub norc: bool,
+
+    /// Start the prompt after running -c
+    #[arg(short = 'i', long)]
+    pub interactive: bool,
+
     /// Run in headless mode
     #[arg(short = 'H', long)]
     pub headless: bool,
@@ -21,4 +29,8 @@ pub struct TishArgs {
     /// Login shell (loads .tish_profile)
     #[arg(short = 'L')]
     pub login: bool,
+
+    /// Print how long each startup phase took
+    #[arg(long)]
+    pub profile: bool,
 }
@@ -0,0 +1,98 @@
+use anyhow::Result;
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    process::ExitCode,
+};
+
+use super::ls::paint;
+use crate::{prelude::*, tty::stdout_is_tty};
+
+// a heredoc stands in for stdin, and like a real stdin only the first `-` gets its contents
+pub fn run(args: &[String], mut heredoc: Option<&str>) -> Result<ExitCode> {
+    let mut numbers = false;
+    let mut color = stdout_is_tty();
+    let mut paths = Vec::new();
+
+    argument! {
+        args: args.iter(),
+        options: {
+            n => numbers = true,
+            C => color = true,
+            h => {
+                print_usage();
+                return Ok(ExitCode::SUCCESS);
+            }
+        },
+        command: |arg: &String| paths.push(arg.to_string()),
+        on_invalid: |opt| {
+            eprintln!("Unknown option: {opt}");
+            print_usage();
+        }
+    }
+
+    if paths.is_empty() {
+        paths.push("-".to_string());
+    }
+
+    let from_heredoc = heredoc.is_some();
+    let mut status = ExitCode::SUCCESS;
+    let mut line_number = 0;
+    let mut stdout = io::stdout().lock();
+
+    for path in &paths {
+        // a lone `-` reaches `command` as a path, and means stdin like cat(1)
+        let mut reader: Box<dyn BufRead> = match path.as_str() {
+            "-" if from_heredoc => Box::new(heredoc.take().unwrap_or_default().as_bytes()),
+            "-" => Box::new(io::stdin().lock()),
+            _ => match File::open(path) {
+                Ok(file) => Box::new(BufReader::new(file)),
+                Err(err) => {
+                    eprintln!("cat: {path}: {err}");
+                    status = ExitCode::FAILURE;
+                    continue;
+                }
+            },
+        };
+
+        let result = match numbers {
+            true => print_numbered(reader, &mut stdout, &mut line_number, color),
+            false => io::copy(&mut reader, &mut stdout).map(|_| ()),
+        };
+
+        match result {
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => break,
+            Err(err) => {
+                eprintln!("cat: {path}: {err}");
+                status = ExitCode::FAILURE;
+            }
+            Ok(()) => {}
+        }
+    }
+
+    stdout.flush()?;
+    Ok(status)
+}
+
+fn print_usage() {
+    println!("usage: cat [-nC] [file ...]");
+}
+
+// numbering carries across files like cat(1), with the same number column colors as `ls -n`
+fn print_numbered(mut reader: Box<dyn BufRead + '_>, out: &mut impl Write, line_number: &mut usize, color: bool) -> io::Result<()> {
+    let light_cyan = paint(color, "\x1b[96m");
+    let grey = paint(color, "\x1b[38;5;240m");
+    let reset = paint(color, "\x1b[0m");
+    let separator = if color { " │ " } else { "\t" };
+
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        *line_number += 1;
+        write!(out, "{light_cyan}{:>6}{grey}{separator}{reset}", line_number)?;
+        out.write_all(&line)?;
+        line.clear();
+    }
+
+    Ok(())
+}
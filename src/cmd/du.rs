@@ -0,0 +1,136 @@
+use anyhow::Result;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use super::ls::format_size;
+use crate::prelude::*;
+
+struct Options {
+    human: bool,
+    depth: Option<usize>,
+}
+
+pub fn run(args: &[String]) -> Result<ExitCode> {
+    let mut human = false;
+    let mut summarize = false;
+    let mut depth: Option<String> = None;
+    let mut expect_depth = false;
+    let mut paths = Vec::new();
+
+    argument! {
+        args: split_depth(args).iter(),
+        options: {
+            h => human = true,
+            s => summarize = true,
+            d => expect_depth = true
+        },
+        long: {
+            "max-depth" (value) => match value {
+                Some(value) => depth = Some(value.to_string()),
+                None => expect_depth = true,
+            },
+            "help" (_value) => {
+                print_usage();
+                return Ok(ExitCode::SUCCESS);
+            }
+        },
+        command: |arg: &String| {
+            match std::mem::take(&mut expect_depth) {
+                true => depth = Some(arg.to_string()),
+                false => paths.push(PathBuf::from(arg)),
+            }
+        },
+        on_invalid: |opt| {
+            eprintln!("Unknown option: {opt}");
+            print_usage();
+        }
+    }
+
+    if expect_depth {
+        eprintln!("du: option '-d' requires a depth");
+        return Ok(ExitCode::FAILURE);
+    }
+
+    let depth = match depth.as_deref().map(str::parse::<usize>) {
+        None if summarize => Some(0),
+        None => None,
+        Some(Ok(depth)) => Some(depth),
+        Some(Err(_)) => {
+            eprintln!("du: invalid depth '{}'", depth.unwrap_or_default());
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    let options = Options { human, depth };
+    let mut ok = true;
+
+    for path in &paths {
+        walk(path, 0, &options, &mut ok);
+    }
+
+    Ok(if ok { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+// `-d1` and `-hd1` carry the depth in the same argument, so it's split off before the flags are read
+fn split_depth(args: &[String]) -> Vec<String> {
+    args.iter()
+        .flat_map(|arg| match arg.find('d') {
+            Some(at) if arg.starts_with('-') && !arg.starts_with("--") && at + 1 < arg.len() => vec![arg[..=at].to_string(), arg[at + 1..].to_string()],
+            _ => vec![arg.clone()],
+        })
+        .collect()
+}
+
+fn print_usage() {
+    println!("usage: du [-hs] [-d depth] [path ...]");
+}
+
+fn print_size(size: u64, path: &Path, options: &Options) {
+    match options.human {
+        true => println!("{}\t{}", format_size(size).trim(), path.display()),
+        false => println!("{size}\t{}", path.display()),
+    }
+}
+
+// sums apparent sizes without following symlinks, printing each directory after its children like du(1).
+// unreadable entries are reported and skipped, clearing `ok`
+fn walk(path: &Path, level: usize, options: &Options, ok: &mut bool) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            eprintln!("du: cannot access '{}': {e}", path.display());
+            *ok = false;
+            return 0;
+        }
+    };
+
+    let mut total = metadata.len();
+    if metadata.is_dir() {
+        match fs::read_dir(path) {
+            Ok(entries) => {
+                for entry in entries.filter_map(Result::ok) {
+                    total += walk(&entry.path(), level + 1, options, ok);
+                }
+            }
+            Err(e) => {
+                eprintln!("du: cannot read directory '{}': {e}", path.display());
+                *ok = false;
+            }
+        }
+    }
+
+    // files only show up when named on the command line, like du(1) without -a
+    if (metadata.is_dir() || level == 0) && options.depth.is_none_or(|depth| level <= depth) {
+        print_size(total, path, options);
+    }
+
+    total
+}
@@ -0,0 +1,256 @@
+use anyhow::{bail, Result};
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use crate::prelude::*;
+
+#[derive(Default, Clone, Copy)]
+struct Flags {
+    recursive: bool,
+    force: bool,
+    interactive: bool,
+}
+
+pub fn cp(args: &[String]) -> Result<ExitCode> {
+    let mut flags = Flags::default();
+    let mut paths = Vec::new();
+
+    argument! {
+        args: args.iter(),
+        options: {
+            r => flags.recursive = true,
+            R => flags.recursive = true,
+            f => (flags.force, flags.interactive) = (true, false),
+            i => (flags.force, flags.interactive) = (false, true),
+            h => {
+                print_usage("cp", "[-rfi] source ... target");
+                return Ok(ExitCode::SUCCESS);
+            }
+        },
+        command: |arg: &String| paths.push(PathBuf::from(arg)),
+        on_invalid: |opt| {
+            eprintln!("Unknown option: {opt}");
+            print_usage("cp", "[-rfi] source ... target");
+        }
+    }
+
+    let Some((sources, target)) = split_target("cp", &paths) else {
+        return Ok(ExitCode::FAILURE);
+    };
+
+    run_each("cp", sources, |source| copy_path(source, &destination(source, target), flags))
+}
+
+pub fn mv(args: &[String]) -> Result<ExitCode> {
+    let mut flags = Flags::default();
+    let mut paths = Vec::new();
+
+    argument! {
+        args: args.iter(),
+        options: {
+            f => (flags.force, flags.interactive) = (true, false),
+            i => (flags.force, flags.interactive) = (false, true),
+            h => {
+                print_usage("mv", "[-fi] source ... target");
+                return Ok(ExitCode::SUCCESS);
+            }
+        },
+        command: |arg: &String| paths.push(PathBuf::from(arg)),
+        on_invalid: |opt| {
+            eprintln!("Unknown option: {opt}");
+            print_usage("mv", "[-fi] source ... target");
+        }
+    }
+
+    let Some((sources, target)) = split_target("mv", &paths) else {
+        return Ok(ExitCode::FAILURE);
+    };
+
+    run_each("mv", sources, |source| move_path(source, &destination(source, target), flags))
+}
+
+pub fn rm(args: &[String]) -> Result<ExitCode> {
+    let mut flags = Flags::default();
+    let mut paths = Vec::new();
+
+    argument! {
+        args: args.iter(),
+        options: {
+            r => flags.recursive = true,
+            R => flags.recursive = true,
+            f => (flags.force, flags.interactive) = (true, false),
+            i => (flags.force, flags.interactive) = (false, true),
+            h => {
+                print_usage("rm", "[-rfi] path ...");
+                return Ok(ExitCode::SUCCESS);
+            }
+        },
+        command: |arg: &String| paths.push(PathBuf::from(arg)),
+        on_invalid: |opt| {
+            eprintln!("Unknown option: {opt}");
+            print_usage("rm", "[-rfi] path ...");
+        }
+    }
+
+    if paths.is_empty() && !flags.force {
+        eprintln!("tish: rm: missing operand");
+        return Ok(ExitCode::FAILURE);
+    }
+
+    run_each("rm", &paths, |path| match path.file_name() {
+        Some(_) => remove_path(path, flags),
+        None => bail!("refusing to remove '{}'", path.display()),
+    })
+}
+
+fn print_usage(name: &str, usage: &str) {
+    println!("usage: {name} {usage}");
+}
+
+// errors are reported per path so one bad source doesn't stop the rest, like coreutils
+fn run_each(name: &str, paths: &[PathBuf], mut op: impl FnMut(&Path) -> Result<()>) -> Result<ExitCode> {
+    let mut status = ExitCode::SUCCESS;
+
+    for path in paths {
+        if let Err(err) = op(path) {
+            eprintln!("tish: {name}: {err}");
+            status = ExitCode::FAILURE;
+        }
+    }
+
+    Ok(status)
+}
+
+fn split_target<'a>(name: &str, paths: &'a [PathBuf]) -> Option<(&'a [PathBuf], &'a Path)> {
+    let Some((target, sources)) = paths.split_last().filter(|(_, sources)| !sources.is_empty()) else {
+        eprintln!("tish: {name}: missing destination operand");
+        return None;
+    };
+
+    if sources.len() > 1 && !target.is_dir() {
+        eprintln!("tish: {name}: target '{}' is not a directory", target.display());
+        return None;
+    }
+
+    Some((sources, target))
+}
+
+fn destination(source: &Path, target: &Path) -> PathBuf {
+    match source.file_name() {
+        Some(name) if target.is_dir() => target.join(name),
+        _ => target.to_path_buf(),
+    }
+}
+
+fn confirm(prompt: &str) -> bool {
+    print!("{prompt} ");
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok_and(|_| answer.trim_start().starts_with(['y', 'Y']))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool { fs::canonicalize(a).is_ok_and(|a| fs::canonicalize(b).is_ok_and(|b| a == b)) }
+
+fn copy_path(source: &Path, target: &Path, flags: Flags) -> Result<()> {
+    let metadata = fs::symlink_metadata(source).map_err(|err| anyhow!("cannot stat '{}': {err}", source.display()))?;
+
+    if same_file(source, target) {
+        bail!("'{}' and '{}' are the same file", source.display(), target.display());
+    }
+
+    if metadata.is_dir() {
+        if !flags.recursive {
+            bail!("-r not specified; omitting directory '{}'", source.display());
+        }
+
+        let parent = target.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        if fs::canonicalize(parent).is_ok_and(|parent| fs::canonicalize(source).is_ok_and(|source| parent.starts_with(source))) {
+            bail!("cannot copy a directory, '{}', into itself", source.display());
+        }
+
+        fs::create_dir_all(target).map_err(|err| anyhow!("cannot create directory '{}': {err}", target.display()))?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_path(&entry.path(), &target.join(entry.file_name()), flags)?;
+        }
+        return Ok(());
+    }
+
+    if fs::symlink_metadata(target).is_ok() {
+        if flags.interactive && !confirm(&format!("tish: cp: overwrite '{}'?", target.display())) {
+            return Ok(());
+        }
+        if flags.force || metadata.file_type().is_symlink() {
+            let _ = fs::remove_file(target);
+        }
+    }
+
+    let copied = match metadata.file_type().is_symlink() {
+        true => fs::read_link(source).and_then(|link| std::os::unix::fs::symlink(link, target)),
+        false => fs::copy(source, target).map(|_| ()),
+    };
+
+    copied.map_err(|err| anyhow!("cannot copy '{}' to '{}': {err}", source.display(), target.display()))
+}
+
+fn move_path(source: &Path, target: &Path, flags: Flags) -> Result<()> {
+    fs::symlink_metadata(source).map_err(|err| anyhow!("cannot stat '{}': {err}", source.display()))?;
+
+    if same_file(source, target) {
+        bail!("'{}' and '{}' are the same file", source.display(), target.display());
+    }
+
+    if flags.interactive && fs::symlink_metadata(target).is_ok() && !confirm(&format!("tish: mv: overwrite '{}'?", target.display())) {
+        return Ok(());
+    }
+
+    match fs::rename(source, target) {
+        // rename can't cross filesystems, so fall back to copying and removing the original
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            let flags = Flags { recursive: true, force: true, interactive: false };
+            copy_path(source, target, flags)?;
+            remove_path(source, flags)
+        }
+        result => result.map_err(|err| anyhow!("cannot move '{}' to '{}': {err}", source.display(), target.display())),
+    }
+}
+
+fn remove_path(path: &Path, flags: Flags) -> Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if flags.force && err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => bail!("cannot remove '{}': {err}", path.display()),
+    };
+
+    if !metadata.is_dir() {
+        if flags.interactive && !confirm(&format!("tish: rm: remove '{}'?", path.display())) {
+            return Ok(());
+        }
+        return fs::remove_file(path).map_err(|err| anyhow!("cannot remove '{}': {err}", path.display()));
+    }
+
+    if !flags.recursive {
+        bail!("cannot remove '{}': Is a directory", path.display());
+    }
+
+    if flags.interactive {
+        if !confirm(&format!("tish: rm: descend into directory '{}'?", path.display())) {
+            return Ok(());
+        }
+        for entry in fs::read_dir(path)? {
+            remove_path(&entry?.path(), flags)?;
+        }
+        if !confirm(&format!("tish: rm: remove directory '{}'?", path.display())) {
+            return Ok(());
+        }
+        return fs::remove_dir(path).map_err(|err| anyhow!("cannot remove '{}': {err}", path.display()));
+    }
+
+    fs::remove_dir_all(path).map_err(|err| anyhow!("cannot remove '{}': {err}", path.display()))
+}
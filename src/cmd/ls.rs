@@ -1,9 +1,10 @@
-#![allow(non_upper_case_globals)]
-
 use anyhow::Result;
 use chrono::{DateTime, Local, TimeZone};
+use git2::Repository;
+use regex::Regex;
 
 use std::{
+    collections::HashMap,
     fs,
     os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
@@ -15,31 +16,136 @@ use crate::{
     cmd::file,
     os::{size::dimensions, user::get_user_by_uid},
     prelude::*,
+    shell::git::{self, FileStatus},
+    template::Template,
+    tty::stdout_is_tty,
 };
 
 struct Entry {
     name: String,
+    bytes: u64,
     size: String,
     modified: String,
     username: String,
     file_type: String,
     color: String,
-    icon: &'static str,
+    icon: String,
+    permissions: String,
+    git: Option<FileStatus>,
+}
+
+struct Options {
+    show_all: bool,
+    raw_bytes: bool,
+    color: bool,
+    icons: bool,
+    git_ignore: bool,
+    git_status: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Layout {
+    Down,
+    Across,
+    Single,
+}
+
+pub struct Theme {
+    border: String,
+    header: String,
+    number: String,
+    name: String,
+    size: String,
+    file_type: String,
     permissions: String,
+    user: String,
+    modified: String,
+}
+
+impl Theme {
+    fn new() -> Self {
+        Self {
+            border: "\x1b[38;5;240m".to_string(),
+            header: "\x1b[33m".to_string(),
+            number: "\x1b[96m".to_string(),
+            name: String::new(),
+            size: "\x1b[36m".to_string(),
+            file_type: "\x1b[95m".to_string(),
+            permissions: "\x1b[92m".to_string(),
+            user: "\x1b[38;5;217m".to_string(),
+            modified: "\x1b[37m".to_string(),
+        }
+    }
+
+    // what --color=never prints with
+    fn plain() -> Self {
+        Self {
+            border: String::new(),
+            header: String::new(),
+            number: String::new(),
+            name: String::new(),
+            size: String::new(),
+            file_type: String::new(),
+            permissions: String::new(),
+            user: String::new(),
+            modified: String::new(),
+        }
+    }
+
+    // config.ls_colors overrides single columns, with values parsed like prompt styles
+    pub fn from_config(colors: Option<HashMap<String, String>>, truecolor: Option<bool>) -> Self {
+        let mut theme = Self::new();
+        let mut tmpl = Template::new("");
+        if let Some(truecolor) = truecolor {
+            tmpl.set_truecolor(truecolor);
+        }
+
+        for (key, style) in colors.unwrap_or_default() {
+            let slot = match key.as_str() {
+                "border" => &mut theme.border,
+                "header" => &mut theme.header,
+                "number" => &mut theme.number,
+                "name" => &mut theme.name,
+                "size" => &mut theme.size,
+                "type" => &mut theme.file_type,
+                "permissions" => &mut theme.permissions,
+                "user" => &mut theme.user,
+                "modified" => &mut theme.modified,
+                _ => {
+                    eprintln!("ls: config.ls_colors.{key} is not a known column");
+                    continue;
+                }
+            };
+
+            match tmpl.style_escape(&style) {
+                Some(escape) => *slot = escape,
+                None => eprintln!("ls: config.ls_colors.{key}: unknown style {style:?}"),
+            }
+        }
+
+        theme
+    }
 }
 
 struct ColumnWidths {
+    icon: usize,
     name: usize,
     size: usize,
     file_type: usize,
     permissions: usize,
 }
 
-pub fn run(args: &Vec<String>) -> Result<ExitCode> {
+pub fn run(args: &Vec<String>, theme: Theme, mut icons: bool) -> Result<ExitCode> {
     let mut table = false;
     let mut numbers = false;
     let mut show_all = false;
     let mut metadata = false;
+    let mut raw_bytes = false;
+    let mut git_ignore = false;
+    let mut color = stdout_is_tty();
+    let mut layout = Layout::Down;
+    let mut matching: Option<String> = None;
+    let mut expect_match = false;
     let mut paths = Vec::new();
 
     argument! {
@@ -49,85 +155,212 @@ pub fn run(args: &Vec<String>) -> Result<ExitCode> {
             n => numbers = true,
             m => metadata = true,
             a => show_all = true,
+            b => raw_bytes = true,
+            C => color = true,
+            1 => layout = Layout::Single,
+            x => layout = Layout::Across,
             h => {
                 print_usage();
                 return Ok(ExitCode::SUCCESS);
             }
         },
-        command: |arg| {
-            paths.push(PathBuf::from(arg))
+        long: {
+            "color" (value) => color = match value {
+                None | Some("always") => true,
+                Some("never") => false,
+                Some("auto") => stdout_is_tty(),
+                Some(value) => {
+                    eprintln!("ls: invalid argument '{value}' for '--color', expected always, never or auto");
+                    return Ok(ExitCode::FAILURE);
+                }
+            },
+            "match" (value) => match value {
+                Some(value) => matching = Some(value.to_string()),
+                None => expect_match = true,
+            },
+            "no-icons" (_value) => icons = false,
+            "git-ignore" (_value) => git_ignore = true
+        },
+        command: |arg: &String| {
+            match std::mem::take(&mut expect_match) {
+                true => matching = Some(arg.to_string()),
+                false => paths.push(arg.to_string()),
+            }
         },
-        on_invalid: |c| {
-            eprintln!("Unknown option: -{c}");
+        on_invalid: |opt| {
+            eprintln!("Unknown option: {opt}");
             print_usage();
         }
     }
 
+    if expect_match {
+        eprintln!("ls: option '--match' requires a pattern");
+        return Ok(ExitCode::FAILURE);
+    }
+
+    let pattern = match matching.as_deref().map(Regex::new).transpose() {
+        Ok(pattern) => pattern,
+        Err(err) => {
+            eprintln!("ls: invalid pattern for '--match': {err}");
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+
     if paths.is_empty() {
-        paths.push(PathBuf::from("."));
+        paths.push(".".to_string());
     }
 
-    for (i, path) in paths.iter().enumerate() {
-        if paths.len() > 1 {
-            if i > 0 {
+    let theme = if color { theme } else { Theme::plain() };
+    let options = Options {
+        show_all,
+        raw_bytes,
+        color,
+        icons,
+        git_ignore,
+        git_status: table,
+    };
+
+    let mut status = ExitCode::SUCCESS;
+    let mut expanded = Vec::new();
+    let mut headers = paths.len() > 1;
+
+    for path in paths {
+        match expand_glob(&path) {
+            Some(matches) if matches.is_empty() => {
+                eprintln!("ls: no matches found: {path}");
+                status = ExitCode::FAILURE;
+            }
+            Some(matches) => expanded.extend(matches),
+            None => match fs::metadata(&path) {
+                Ok(_) => expanded.push(PathBuf::from(path)),
+                Err(e) => {
+                    eprintln!("ls: cannot access '{path}': {e}");
+                    status = ExitCode::FAILURE;
+                }
+            },
+        }
+    }
+
+    headers |= expanded.len() > 1;
+    let (files, dirs): (Vec<_>, Vec<_>) = expanded.iter().partition(|path| fs::metadata(path).is_ok_and(|meta| !meta.is_dir()));
+
+    let print = |entries: &[Entry]| -> std::io::Result<()> {
+        match layout {
+            _ if table => {
+                print_table_entries(entries, metadata, numbers, color, &theme)?;
+                print_total(entries, raw_bytes, color, &theme);
+            }
+            Layout::Single => print_single_column(entries, color, &theme),
+            _ => print_standard_entries(entries, layout, color, &theme)?,
+        }
+        Ok(())
+    };
+
+    let mut file_entries = Vec::new();
+    for path in &files {
+        // like directory entries, explicit files are matched on their name rather than the path given
+        let name = path.to_string_lossy();
+        let file_name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+        if !pattern.as_ref().is_none_or(|re| re.is_match(&file_name)) {
+            continue;
+        }
+
+        match fs::metadata(path).and_then(|meta| format_entry(&name, &meta, &options)) {
+            Ok(entry) => file_entries.push(entry),
+            Err(e) => eprintln!("Error reading {}: {}", path.display(), e),
+        }
+    }
+
+    if !file_entries.is_empty() {
+        sort_entries(&mut file_entries);
+        print(&file_entries)?;
+    }
+
+    for (i, path) in dirs.iter().enumerate() {
+        if headers {
+            if i > 0 || !file_entries.is_empty() {
                 println!();
             }
             println!("{}:", path.display());
         }
 
-        match read_directory(path, show_all) {
-            Ok(entries) => {
-                if table {
-                    print_table_entries(&entries, metadata, numbers)?;
-                } else {
-                    print_standard_entries(&entries)?;
-                }
-            }
+        match read_directory(path, pattern.as_ref(), &options) {
+            Ok(entries) => print(&entries)?,
             Err(e) => {
                 eprintln!("Error reading {}: {}", path.display(), e);
+                status = ExitCode::FAILURE;
             }
         }
     }
 
-    Ok(ExitCode::SUCCESS)
+    Ok(status)
+}
+
+// None when the argument has no glob characters or names a real file, so `ls '[x]'` still works
+fn expand_glob(path: &str) -> Option<Vec<PathBuf>> {
+    if !path.contains(['*', '?', '[']) || fs::symlink_metadata(path).is_ok() {
+        return None;
+    }
+
+    let mut matches: Vec<PathBuf> = glob::glob(path).ok()?.filter_map(Result::ok).collect();
+    matches.sort();
+    Some(matches)
 }
 
 fn print_usage() {
-    println!("usage: ls [-alnm] [path ...]");
+    println!("usage: ls [-abnlmC1x] [--color=always|never|auto] [--match=regex] [--no-icons] [--git-ignore] [path|glob ...]");
 }
 
-fn read_directory(path: &Path, show_all: bool) -> std::io::Result<Vec<Entry>> {
-    let mut entries: Vec<_> = fs::read_dir(path)?.filter_map(Result::ok).filter(|entry| show_all || !is_hidden(entry)).collect();
+// with git_ignore, entries the surrounding repository ignores are hidden, and git_status looks up every
+// entry's status once for the whole directory. outside of a repo neither does anything
+fn read_directory(path: &Path, pattern: Option<&Regex>, options: &Options) -> std::io::Result<Vec<Entry>> {
+    let ignore = match options.git_ignore.then(|| Repository::discover(path).ok()).flatten() {
+        Some(repo) => Some((repo, fs::canonicalize(path)?)),
+        None => None,
+    };
+    let mut statuses = options.git_status.then(|| git::dir_statuses(path)).flatten();
+
+    let mut entries: Vec<_> = fs::read_dir(path)?
+        .filter_map(Result::ok)
+        .filter(|entry| options.show_all || !is_hidden(entry))
+        .filter(|entry| pattern.is_none_or(|re| re.is_match(&entry.file_name().to_string_lossy())))
+        .filter(|entry| ignore.as_ref().is_none_or(|(repo, absolute)| !repo.is_path_ignored(absolute.join(entry.file_name())).unwrap_or(false)))
+        .collect();
 
     entries.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
 
     let mut result = Vec::new();
     for entry in entries {
         let metadata = entry.metadata()?;
-        if let Ok(formatted_entry) = format_entry(&entry, &metadata) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Ok(mut formatted_entry) = format_entry(&name, &metadata, options) {
+            formatted_entry.git = statuses.as_mut().map(|statuses| statuses.remove(&name).unwrap_or(FileStatus { letter: ' ', staged: false }));
             result.push(formatted_entry);
         }
     }
 
-    result.sort_by(|a, b| if a.file_type != b.file_type { a.file_type.cmp(&b.file_type) } else { a.name.cmp(&b.name) });
-
+    sort_entries(&mut result);
     Ok(result)
 }
 
-fn format_entry(entry: &fs::DirEntry, metadata: &fs::Metadata) -> std::io::Result<Entry> {
+fn sort_entries(entries: &mut [Entry]) { entries.sort_by(|a, b| if a.file_type != b.file_type { a.file_type.cmp(&b.file_type) } else { a.name.cmp(&b.name) }); }
+
+// without icons the glyph and its trailing space are left out, for terminals without a nerd font
+fn format_entry(name: &str, metadata: &fs::Metadata, options: &Options) -> std::io::Result<Entry> {
     let mode = metadata.mode();
-    let name = entry.file_name().to_string_lossy().into_owned();
-    let file_info = file::FileInfo::new(&metadata, &name);
+    let file_info = file::FileInfo::new(&metadata, name);
 
     Ok(Entry {
         name: file_info.display_name,
-        size: format_size(metadata.len()),
+        bytes: metadata.len(),
+        size: if options.raw_bytes { metadata.len().to_string() } else { format_size(metadata.len()) },
         modified: format_time(metadata.modified()?),
         username: get_username(metadata.uid()),
         file_type: file_info.file_type.to_string(),
-        icon: file_info.icon.get_glyph(),
-        color: file_info.icon.get_color(),
+        icon: if options.icons { format!("{} ", file_info.icon.get_glyph()) } else { String::new() },
+        color: if options.color && options.icons { file_info.icon.get_color() } else { String::new() },
         permissions: format_permissions(mode),
+        git: None,
     })
 }
 
@@ -135,6 +368,7 @@ fn get_username(uid: u32) -> String { get_user_by_uid(uid).map(|user| user.name(
 
 fn calculate_column_widths(entries: &[Entry]) -> ColumnWidths {
     let mut widths = ColumnWidths {
+        icon: 0,
         name: 4,
         size: 4,
         file_type: 4,
@@ -142,6 +376,7 @@ fn calculate_column_widths(entries: &[Entry]) -> ColumnWidths {
     };
 
     for entry in entries {
+        widths.icon = widths.icon.max(entry.icon.chars().count());
         widths.name = widths.name.max(entry.name.len());
         widths.size = widths.size.max(entry.size.len());
         widths.file_type = widths.file_type.max(entry.file_type.len());
@@ -150,13 +385,22 @@ fn calculate_column_widths(entries: &[Entry]) -> ColumnWidths {
     widths
 }
 
-fn print_standard_entries(entries: &[Entry]) -> std::io::Result<()> {
+// escape codes are dropped entirely when color is off, so piped output stays plain text
+pub fn paint(enabled: bool, code: &'static str) -> &'static str {
+    match enabled {
+        true => code,
+        false => "",
+    }
+}
+
+fn print_standard_entries(entries: &[Entry], layout: Layout, color: bool, theme: &Theme) -> std::io::Result<()> {
     if entries.is_empty() {
         return Ok(());
     }
 
     let max_name_len = entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
-    let min_col_width = max_name_len + 3;
+    let icon_width = entries.iter().map(|e| e.icon.chars().count()).max().unwrap_or(0);
+    let min_col_width = max_name_len + icon_width + 1;
 
     let terminal_width = match dimensions() {
         Some((w, _)) if w >= min_col_width => w,
@@ -166,26 +410,27 @@ fn print_standard_entries(entries: &[Entry]) -> std::io::Result<()> {
     let num_columns = std::cmp::max(1, terminal_width / min_col_width);
     let num_rows = (entries.len() + num_columns - 1) / num_columns;
 
+    let index = |row: usize, col: usize| match layout {
+        Layout::Across => row * num_columns + col,
+        _ => col * num_rows + row,
+    };
+
     let mut column_widths = vec![0; num_columns];
-    for (idx, entry) in entries.iter().enumerate() {
-        let col = idx % num_columns;
-        if col < num_columns {
-            column_widths[col] = std::cmp::max(column_widths[col], entry.name.len() + 3);
+    for (col, width) in column_widths.iter_mut().enumerate() {
+        for row in 0..num_rows {
+            if let Some(entry) = entries.get(index(row, col)) {
+                *width = std::cmp::max(*width, entry.name.len() + icon_width + 1);
+            }
         }
     }
 
     for row in 0..num_rows {
         for col in 0..num_columns {
-            let idx = row * num_columns + col;
-            if idx >= entries.len() {
-                break;
-            }
-
-            let entry = &entries[idx];
-            print!("{}{} \x1b[0m{}", entry.color, entry.icon, entry.name);
+            let Some(entry) = entries.get(index(row, col)) else { continue };
+            print!("{}{}{}{}{}{}", entry.color, entry.icon, paint(color, "\x1b[0m"), theme.name, entry.name, paint(color, "\x1b[0m"));
 
-            if col < num_columns - 1 && idx < entries.len() {
-                let spaces = column_widths[col].saturating_sub(entry.name.len() + 3);
+            if col < num_columns - 1 && index(row, col + 1) < entries.len() {
+                let spaces = column_widths[col].saturating_sub(entry.name.len() + icon_width + 1);
                 print!("{}", " ".repeat(spaces + 3));
             }
         }
@@ -195,25 +440,31 @@ fn print_standard_entries(entries: &[Entry]) -> std::io::Result<()> {
     Ok(())
 }
 
-fn print_table_entries(entries: &[Entry], show_metadata: bool, show_numbers: bool) -> std::io::Result<()> {
-    const grey: &'static str = "\x1b[38;5;240m";
-    const yellow: &'static str = "\x1b[33m";
-    const cyan: &'static str = "\x1b[36m";
-    const light_pink: &'static str = "\x1b[38;5;217m";
-    const light_cyan: &'static str = "\x1b[96m";
-    const light_grey: &'static str = "\x1b[37m";
-    const light_green: &'static str = "\x1b[92m";
-    const light_magenta: &'static str = "\x1b[95m";
-    const reset: &'static str = "\x1b[0m";
+// only the names, without icons, so the output can be piped into other commands
+fn print_single_column(entries: &[Entry], color: bool, theme: &Theme) {
+    for entry in entries {
+        println!("{}{}{}", theme.name, entry.name, paint(color, "\x1b[0m"));
+    }
+}
+
+fn print_table_entries(entries: &[Entry], show_metadata: bool, show_numbers: bool, color: bool, theme: &Theme) -> std::io::Result<()> {
+    let grey = &theme.border;
+    let yellow = &theme.header;
+    let reset = paint(color, "\x1b[0m");
 
     let widths = calculate_column_widths(entries);
+    let show_git = entries.iter().any(|entry| entry.git.is_some());
     let num_width = if show_numbers { entries.len().to_string().len().max(1) } else { 0 };
 
     let mut header = format!("{}╭", grey);
     if show_numbers {
         header.push_str(&format!("{}┬", "─".repeat(num_width + 2)));
     }
-    header.push_str(&format!("{}┬{}", "─".repeat(widths.name + 4), "─".repeat(widths.size + 2)));
+    header.push_str(&format!("{}┬", "─".repeat(widths.name + widths.icon + 2)));
+    if show_git {
+        header.push_str(&format!("{}┬", "─".repeat(5)));
+    }
+    header.push_str(&"─".repeat(widths.size + 2));
 
     if show_metadata {
         header.push_str(&format!(
@@ -231,19 +482,13 @@ fn print_table_entries(entries: &[Entry], show_metadata: bool, show_numbers: boo
 
     let mut titles = format!("{}│", grey);
     if show_numbers {
-        titles.push_str(&format!("{} {:<width_num$} {}│", cyan, "#", grey, width_num = num_width));
-    }
-    titles.push_str(&format!(
-        "{} {:<width_name$} {}│{} {:<width_size$} {}│",
-        yellow,
-        "name",
-        grey,
-        yellow,
-        "size",
-        grey,
-        width_name = widths.name + 2,
-        width_size = widths.size
-    ));
+        titles.push_str(&format!("{} {:<width_num$} {}│", theme.size, "#", grey, width_num = num_width));
+    }
+    titles.push_str(&format!("{} {:<width_name$} {}│", yellow, "name", grey, width_name = widths.name + widths.icon));
+    if show_git {
+        titles.push_str(&format!("{} git {}│", yellow, grey));
+    }
+    titles.push_str(&format!("{} {:<width_size$} {}│", yellow, "size", grey, width_size = widths.size));
 
     if show_metadata {
         titles.push_str(&format!(
@@ -268,7 +513,11 @@ fn print_table_entries(entries: &[Entry], show_metadata: bool, show_numbers: boo
     if show_numbers {
         separator.push_str(&format!("{}┼", "─".repeat(num_width + 2)));
     }
-    separator.push_str(&format!("{}┼{}", "─".repeat(widths.name + 4), "─".repeat(widths.size + 2)));
+    separator.push_str(&format!("{}┼", "─".repeat(widths.name + widths.icon + 2)));
+    if show_git {
+        separator.push_str(&format!("{}┼", "─".repeat(5)));
+    }
+    separator.push_str(&"─".repeat(widths.size + 2));
 
     if show_metadata {
         separator.push_str(&format!(
@@ -287,40 +536,46 @@ fn print_table_entries(entries: &[Entry], show_metadata: bool, show_numbers: boo
     for (idx, entry) in entries.iter().enumerate() {
         let mut line = format!("{}│", grey);
         if show_numbers {
-            line.push_str(&format!("{} {:<width_num$} {}│", light_cyan, idx, grey, width_num = num_width));
+            line.push_str(&format!("{} {:<width_num$} {}│", theme.number, idx, grey, width_num = num_width));
         }
         line.push_str(&format!(
-            "{} {}{}{} {:<width_name$} {}│{} {:>width_size$} {}│",
+            "{} {}{}{}{}{:<width_name$} {}│",
             reset,
             entry.color,
             entry.icon,
-            "\x1b[0m",
+            reset,
+            theme.name,
             entry.name,
             grey,
-            cyan,
-            entry.size,
-            grey,
-            width_name = widths.name,
-            width_size = widths.size
+            width_name = widths.name
         ));
 
+        if show_git {
+            let (letter, git_color) = match &entry.git {
+                Some(status) => (status.letter, paint(color, status.color())),
+                None => (' ', ""),
+            };
+            line.push_str(&format!("{}  {}  {}│", git_color, letter, grey));
+        }
+        line.push_str(&format!("{} {:>width_size$} {}│", theme.size, entry.size, grey, width_size = widths.size));
+
         if show_metadata {
             line.push_str(&format!(
                 "{} {:<width_type$} {}│{} {:<width_perm$} {}│{} {:<10} {}│",
-                light_magenta,
+                theme.file_type,
                 entry.file_type,
                 grey,
-                light_green,
+                theme.permissions,
                 entry.permissions,
                 grey,
-                light_pink,
+                theme.user,
                 entry.username,
                 grey,
                 width_type = widths.file_type,
                 width_perm = widths.permissions
             ));
         }
-        line.push_str(&format!("{} {:<14} {}│{}", light_grey, entry.modified, grey, reset));
+        line.push_str(&format!("{} {:<14} {}│{}", theme.modified, entry.modified, grey, reset));
         println!("{}", line);
     }
 
@@ -328,7 +583,11 @@ fn print_table_entries(entries: &[Entry], show_metadata: bool, show_numbers: boo
     if show_numbers {
         footer.push_str(&format!("{}┴", "─".repeat(num_width + 2)));
     }
-    footer.push_str(&format!("{}┴{}", "─".repeat(widths.name + 4), "─".repeat(widths.size + 2)));
+    footer.push_str(&format!("{}┴", "─".repeat(widths.name + widths.icon + 2)));
+    if show_git {
+        footer.push_str(&format!("{}┴", "─".repeat(5)));
+    }
+    footer.push_str(&"─".repeat(widths.size + 2));
 
     if show_metadata {
         footer.push_str(&format!(
@@ -347,7 +606,15 @@ fn print_table_entries(entries: &[Entry], show_metadata: bool, show_numbers: boo
     Ok(())
 }
 
-fn format_size(size: u64) -> String {
+fn print_total(entries: &[Entry], raw_bytes: bool, color: bool, theme: &Theme) {
+    let total: u64 = entries.iter().map(|entry| entry.bytes).sum();
+    let size = if raw_bytes { format!("{total} bytes") } else { format_size(total).trim().to_string() };
+    let noun = if entries.len() == 1 { "entry" } else { "entries" };
+
+    println!("{}{} {noun}, {size} total{}", theme.border, entries.len(), paint(color, "\x1b[0m"));
+}
+
+pub fn format_size(size: u64) -> String {
     if size >= 1024 * 1024 * 1024 {
         format!("{:>5.1}gb", size as f64 / (1024.0 * 1024.0 * 1024.0))
     } else if size >= 1024 * 1024 {
@@ -372,7 +639,7 @@ fn format_time(time: SystemTime) -> String {
         .replace("PM", "pm")
 }
 
-fn is_hidden(entry: &fs::DirEntry) -> bool { entry.file_name().as_encoded_bytes().first().map(|&b| b == b'.').unwrap_or(false) }
+pub fn is_hidden(entry: &fs::DirEntry) -> bool { entry.file_name().as_encoded_bytes().first().map(|&b| b == b'.').unwrap_or(false) }
 
 fn format_permissions(mode: u32) -> String {
     let mut result = String::with_capacity(10);
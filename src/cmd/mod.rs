@@ -1,3 +1,7 @@
+pub mod cat;
+pub mod du;
 pub mod file;
+pub mod fileops;
 pub mod icons;
 pub mod ls;
+pub mod tree;
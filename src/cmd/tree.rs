@@ -0,0 +1,164 @@
+use anyhow::Result;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use super::{
+    file::FileInfo,
+    ls::{is_hidden, paint},
+};
+
+use crate::{prelude::*, tty::stdout_is_tty};
+
+struct Options {
+    show_all: bool,
+    color: bool,
+    depth: Option<usize>,
+}
+
+#[derive(Default)]
+struct Counts {
+    dirs: usize,
+    files: usize,
+}
+
+pub fn run(args: &[String]) -> Result<ExitCode> {
+    let mut show_all = false;
+    let mut color = stdout_is_tty();
+    let mut level: Option<String> = None;
+    let mut expect_level = false;
+    let mut paths = Vec::new();
+
+    argument! {
+        args: args.iter(),
+        options: {
+            a => show_all = true,
+            C => color = true,
+            L => expect_level = true,
+            h => {
+                print_usage();
+                return Ok(ExitCode::SUCCESS);
+            }
+        },
+        long: {
+            "level" (value) => match value {
+                Some(value) => level = Some(value.to_string()),
+                None => expect_level = true,
+            }
+        },
+        command: |arg: &String| {
+            match std::mem::take(&mut expect_level) {
+                true => level = Some(arg.to_string()),
+                false => paths.push(PathBuf::from(arg)),
+            }
+        },
+        on_invalid: |opt| {
+            eprintln!("Unknown option: {opt}");
+            print_usage();
+        }
+    }
+
+    if expect_level {
+        eprintln!("tree: option '-L' requires a depth");
+        return Ok(ExitCode::FAILURE);
+    }
+
+    let depth = match level.as_deref().map(str::parse::<usize>) {
+        None => None,
+        Some(Ok(depth)) if depth > 0 => Some(depth),
+        Some(_) => {
+            eprintln!("tree: invalid level '{}', must be greater than 0", level.unwrap_or_default());
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    let options = Options { show_all, color, depth };
+    let mut counts = Counts::default();
+    let mut status = ExitCode::SUCCESS;
+
+    for path in &paths {
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("tree: cannot access '{}': {e}", path.display());
+                status = ExitCode::FAILURE;
+                continue;
+            }
+        };
+
+        let name = path.to_string_lossy();
+        let info = FileInfo::new(&metadata, &name);
+        println!("{}{} {}{}", info_color(&info, color), info.icon.get_glyph(), paint(color, "\x1b[0m"), info.display_name);
+
+        match metadata.is_dir() {
+            true => print_children(path, "", 1, &options, &mut counts),
+            false => counts.files += 1,
+        }
+    }
+
+    let dirs = if counts.dirs == 1 { "directory" } else { "directories" };
+    let files = if counts.files == 1 { "file" } else { "files" };
+    println!("\n{} {dirs}, {} {files}", counts.dirs, counts.files);
+
+    Ok(status)
+}
+
+fn print_usage() {
+    println!("usage: tree [-aC] [-L depth] [path ...]");
+}
+
+fn info_color(info: &FileInfo, color: bool) -> String {
+    match color {
+        true => info.icon.get_color(),
+        false => String::new(),
+    }
+}
+
+fn print_children(path: &Path, prefix: &str, level: usize, options: &Options, counts: &mut Counts) {
+    let grey = paint(options.color, "\x1b[38;5;240m");
+    let reset = paint(options.color, "\x1b[0m");
+
+    let mut entries: Vec<_> = match fs::read_dir(path) {
+        Ok(entries) => entries.filter_map(Result::ok).filter(|entry| options.show_all || !is_hidden(entry)).collect(),
+        Err(e) => {
+            println!("{prefix}{grey}└── {reset}[error opening dir: {e}]");
+            return;
+        }
+    };
+
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for (idx, entry) in entries.iter().enumerate() {
+        let last = idx == entries.len() - 1;
+        let Ok(metadata) = entry.metadata() else { continue };
+
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let info = FileInfo::new(&metadata, &name);
+        let connector = if last { "└── " } else { "├── " };
+
+        println!(
+            "{prefix}{grey}{connector}{}{} {reset}{}",
+            info_color(&info, options.color),
+            info.icon.get_glyph(),
+            info.display_name
+        );
+
+        if !metadata.is_dir() {
+            counts.files += 1;
+            continue;
+        }
+
+        counts.dirs += 1;
+        if options.depth.is_none_or(|depth| level < depth) {
+            let child_prefix = format!("{prefix}{grey}{}{reset}", if last { "    " } else { "│   " });
+            print_children(&entry.path(), &child_prefix, level + 1, options, counts);
+        }
+    }
+}
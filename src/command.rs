@@ -1,22 +1,33 @@
 pub use super::lua::LuaState;
 
 use crate::{
-    cmd,
+    cmd, env_set_sync,
+    jobs::parse_signal,
     models::{Command, InternalCommand},
     os::env::EnvManager,
-    shell::{signals::*, tokenizer::Tokenizer, TishShell},
+    readline::AsyncLineReader,
+    shell::{
+        alias,
+        signals::*,
+        tokenizer::{Heredoc, Tokenizer},
+        TishShell,
+    },
 };
 
 use anyhow::{anyhow, Result};
-use tokio::task;
+use libc::id_t;
+use nix::sys::signal::Signal;
+use tokio::{io::AsyncWriteExt, task};
 
 use std::{
+    collections::HashMap,
     env,
     path::{Path, PathBuf},
-    process::ExitCode,
-    sync::atomic::Ordering,
+    process::{ExitCode, Stdio},
+    sync::{atomic::Ordering, Mutex},
 };
 
+#[derive(Clone)]
 pub struct TishCommand {
     args: Vec<String>,
     background: bool,
@@ -25,35 +36,89 @@ pub struct TishCommand {
     pub pipe_to: Option<Box<TishCommand>>,
     pub redirect_in: Option<String>,
     pub redirect_out: Option<(String, bool)>,
+    pub heredoc: Option<String>,
 }
 
 impl TishCommand {
+    // the expanded form `set -x` prints, including every pipe stage, with arguments quoted back where needed
+    pub fn trace_line(&self) -> String {
+        let quote = |arg: &String| match arg.is_empty() || arg.contains(char::is_whitespace) {
+            true => Self::quote_alias(arg),
+            false => arg.to_string(),
+        };
+        let line = std::iter::once(&self.program).chain(&self.args).map(quote).collect::<Vec<_>>().join(" ");
+
+        match &self.pipe_to {
+            Some(next) => format!("{line} | {}", next.trace_line()),
+            None => line,
+        }
+    }
+
     pub fn parse(input: &str) -> Vec<Self> {
+        let (line, heredoc) = match Heredoc::split(input) {
+            Some((line, heredoc)) => (line, Some(heredoc)),
+            None => (input.to_string(), None),
+        };
+        let input = Self::strip_comment(&line);
+
         if input.trim().is_empty() {
             return vec![];
         }
 
+        // every later pipe stage resolves its own alias, and each stage is only expanded once its alias is,
+        // so `$VAR` inside an alias body expands too
         let parse_command = |cmd_str: &str| -> Option<Self> {
-            let expanded = EnvManager::new(cmd_str).expand();
-
-            if expanded.contains('|') {
-                let parts: Vec<&str> = expanded.split('|').map(str::trim).filter(|s| !s.is_empty()).collect();
-
-                let mut final_cmd = None;
-                for part in parts.into_iter().rev() {
-                    let mut current_cmd = Self::parse_single_command(Tokenizer::new(part));
-                    if let Some(next_cmd) = final_cmd {
-                        current_cmd.pipe_to = Some(Box::new(next_cmd));
-                    }
-                    final_cmd = Some(current_cmd);
+            let stages: Vec<&str> = cmd_str.split('|').map(str::trim).filter(|s| !s.is_empty()).collect();
+
+            let mut final_cmd = None;
+            for (idx, stage) in stages.into_iter().enumerate().rev() {
+                let stage = if idx == 0 { stage.to_string() } else { alias::resolve_command(stage.to_string()) };
+                let mut current_cmd = Self::parse_single_command(Tokenizer::new(&EnvManager::new(&stage).expand()));
+                if let Some(next_cmd) = final_cmd {
+                    current_cmd.pipe_to = Some(Box::new(next_cmd));
                 }
-                final_cmd
-            } else {
-                Some(Self::parse_single_command(Tokenizer::new(&expanded)))
+                final_cmd = Some(current_cmd);
             }
+            final_cmd
         };
 
-        input.split("&&").map(str::trim).filter(|s| !s.is_empty()).filter_map(parse_command).collect()
+        // the alias resolves on the whole line before splitting, so one can contain `&&` or a pipe, and every later
+        // segment then resolves its own first word. global aliases already covered the whole line. resolving only
+        // rewrites the start of a segment, so the heredoc still belongs to the same segment counted from the end
+        let heredoc_segment = heredoc.as_ref().map(|heredoc| input[heredoc.position.min(input.len())..].matches("&&").count());
+        let resolved = alias::resolve_command(alias::resolve_globals(input));
+        let segments: Vec<&str> = resolved.split("&&").collect();
+        let mut commands = Vec::new();
+
+        for (idx, segment) in segments.iter().enumerate() {
+            let segment = if idx == 0 { segment.trim().to_string() } else { alias::resolve_command(segment.to_string()) };
+            if let Some(mut command) = Some(segment.as_str()).filter(|s| !s.is_empty()).and_then(parse_command) {
+                if let Some(heredoc) = heredoc.as_ref().filter(|_| heredoc_segment == Some(segments.len() - 1 - idx)) {
+                    command.heredoc = Some(heredoc.contents());
+                }
+                commands.push(command);
+            }
+        }
+
+        commands
+    }
+
+    // a `#` starts a comment only outside quotes and at the start of a word
+    fn strip_comment(input: &str) -> &str {
+        let mut quote_char = None;
+        let mut prev = None;
+
+        for (i, c) in input.char_indices() {
+            match c {
+                '\'' | '"' if quote_char.is_none() => quote_char = Some(c),
+                c if Some(c) == quote_char => quote_char = None,
+                '#' if quote_char.is_none() && prev.is_none_or(char::is_whitespace) => return &input[..i],
+                _ => {}
+            }
+            prev = Some(c);
+        }
+
+        input
     }
 
     pub async fn execute(&self, shell: &TishShell) -> Result<ExitCode> {
@@ -63,9 +128,11 @@ impl TishCommand {
         if self.program.as_str() == "tish" && self.args.len() != 0 {
             let result = match internal_command {
                 InternalCommand::Fg => self.handle_builtin_fg().await?,
+                InternalCommand::Bg => self.handle_builtin_bg()?,
+                InternalCommand::Disown => self.handle_builtin_disown()?,
                 InternalCommand::Jobs => crate::JOBS.lock().expect("Able to lock jobs").list_jobs().await?,
                 InternalCommand::Help => Self::handle_builtin_help()?,
-                InternalCommand::Kill => self.handle_builtin_kill().await?,
+                InternalCommand::Kill => Self::handle_builtin_kill(&self.args[1..]).await?,
                 InternalCommand::External => self.execute_external(shell).await?,
                 InternalCommand::Script => shell.lua.eval_file(std::path::Path::new(&self.program))?,
 
@@ -80,19 +147,46 @@ impl TishCommand {
 
         let result = match command {
             Command::Fg => self.handle_builtin_fg().await?,
-            Command::Cd => self.handle_builtin_cd()?,
+            Command::Bg => self.handle_builtin_bg()?,
+            Command::Disown => self.handle_builtin_disown()?,
+            Command::Cd => self.handle_builtin_cd(shell)?,
+            Command::Dirs => Self::handle_builtin_dirs()?,
+            Command::Pushd => self.handle_builtin_pushd()?,
+            Command::Popd => Self::handle_builtin_popd()?,
             Command::Help => Self::handle_builtin_help()?,
+            Command::History => self.handle_builtin_history()?,
+            Command::Alias => self.handle_builtin_alias()?,
+            Command::Unalias => self.handle_builtin_unalias()?,
+            Command::Set => self.handle_builtin_set(shell)?,
             Command::Jobs => crate::JOBS.lock().expect("Able to lock jobs").list_jobs().await?,
-            Command::External => self.execute_external(shell).await?,
+            Command::Kill => Self::handle_builtin_kill(&self.args).await?,
+            Command::External => match shell.lua.call_command(&self.program, &self.args)? {
+                Some(code) => code,
+                None => self.execute_external(shell).await?,
+            },
             Command::Script => shell.lua.eval_file(std::path::Path::new(&self.program))?,
-            Command::Source => shell.lua.eval_file(Path::new(&self.args.get(0).ok_or_else(|| anyhow!("Could not determine source file"))?))?,
+            Command::Source => self.handle_builtin_source(shell).await?,
 
             Command::Ls => match shell.lua.get_config_value("use_tish_ls")? {
-                true => cmd::ls::run(&self.args)?,
+                true => {
+                    let theme = cmd::ls::Theme::from_config(shell.lua.get_config_value("ls_colors")?, shell.lua.get_config_value("truecolor")?);
+                    cmd::ls::run(&self.args, theme, shell.lua.get_config_value("use_icons")?)?
+                }
                 false => self.execute_external(shell).await?,
             },
 
+            // opt-in since they only cover common flags, and du sums apparent sizes where du(1) counts disk blocks
+            Command::Cp | Command::Mv | Command::Rm | Command::Cat | Command::Du if !shell.lua.get_config_value::<bool>("use_tish_coreutils")? => self.execute_external(shell).await?,
+            Command::Cp => cmd::fileops::cp(&self.args)?,
+            Command::Mv => cmd::fileops::mv(&self.args)?,
+            Command::Rm => cmd::fileops::rm(&self.args)?,
+            Command::Cat => cmd::cat::run(&self.args, self.heredoc.as_deref())?,
+            Command::Du => cmd::du::run(&self.args)?,
+
+            Command::Tree => cmd::tree::run(&self.args)?,
+
             Command::Exit => {
+                shell.hangup_jobs();
                 CURRENT_FOREGROUND_PID.store(-1, Ordering::SeqCst);
 
                 if let Some(handler) = GLOBAL_SIGNAL_HANDLER.get() {
@@ -117,18 +211,16 @@ impl TishCommand {
     async fn execute_external(&self, shell: &TishShell) -> Result<ExitCode> {
         let auto_cd = shell.lua.get_config_value("auto_cd")?;
 
-        let path_str = if self.program.starts_with("~/") {
-            dirs::home_dir()
-                .map(|mut p| {
-                    p.push(&self.program[2..]);
-                    p
-                })
-                .unwrap_or_else(|| PathBuf::from(&self.program))
-        } else {
-            PathBuf::from(&self.program)
-        };
+        let mut path_str = PathBuf::from(EnvManager::expand_tilde(&self.program));
+
+        if path_str.is_relative() {
+            if let Ok(current_dir) = env::current_dir() {
+                path_str = current_dir.join(path_str);
+            }
+        }
 
-        if auto_cd && path_str.is_dir() {
+        // a bare name like `src` still runs the command when one exists on PATH
+        if auto_cd && path_str.is_dir() && (self.program.contains('/') || !Self::in_path(&self.program)) {
             return TishCommand {
                 program: "cd".to_string(),
                 args: vec![path_str.to_string_lossy().into_owned()],
@@ -136,8 +228,27 @@ impl TishCommand {
                 pipe_to: None,
                 redirect_in: None,
                 redirect_out: None,
+                heredoc: None,
             }
-            .handle_builtin_cd();
+            .handle_builtin_cd(shell);
+        }
+
+        // `alias -s rs=nvim` runs `nvim foo.rs` when the command is `foo.rs`
+        if let Some(command) = path_str.is_file().then(|| alias::resolve_suffix(&self.program)).flatten() {
+            let mut parts = command.split_whitespace().map(str::to_string);
+            let program = parts.next().unwrap_or_default();
+
+            let mut args: Vec<String> = parts.collect();
+            args.push(self.program.clone());
+            args.extend(self.args.iter().cloned());
+
+            // only the program and arguments change, pipes, redirects and heredocs carry over
+            let command = TishCommand { program, args, ..self.clone() };
+
+            return match command.background {
+                true => command.spawn_background_job().map(|_| ExitCode::SUCCESS),
+                false => command.spawn_foreground_job(&shell.signal_handler).await,
+            };
         }
 
         if self.background {
@@ -148,16 +259,40 @@ impl TishCommand {
         }
     }
 
+    fn in_path(program: &str) -> bool {
+        env::var_os("PATH").is_some_and(|paths| env::split_paths(&paths).any(|path| path.join(program).is_file()))
+    }
+
     fn spawn_background_job(&self) -> Result<()> {
         let program = self.program.clone();
         let args = self.args.clone();
 
-        task::spawn(async move {
-            let mut handle = tokio::process::Command::new(&program);
-            handle.args(&args);
+        let mut handle = match (program.as_str(), args.split_first()) {
+            ("nohup", Some((nohup_program, nohup_args))) => {
+                let mut handle = tokio::process::Command::new(nohup_program);
+                let output = Self::open_nohup_output()?;
+
+                handle.args(nohup_args).stdout(output.try_clone()?).stderr(output);
+                unsafe {
+                    handle.pre_exec(|| {
+                        libc::signal(libc::SIGHUP, libc::SIG_IGN);
+                        Ok(())
+                    });
+                }
+                handle
+            }
+            _ => {
+                let mut handle = tokio::process::Command::new(&program);
+                handle.args(&args).stdout(Stdio::null()).stderr(Stdio::null());
+                handle
+            }
+        };
+        let heredoc = self.heredoc.clone();
+        handle.stdin(if heredoc.is_some() { Stdio::piped() } else { Stdio::null() });
 
+        task::spawn(async move {
             if let Ok(mut manager) = crate::JOBS.try_lock() {
-                if let Err(err) = manager.add_job(&mut handle, program, args) {
+                if let Err(err) = manager.add_job(&mut handle, program, args, heredoc) {
                     eprintln!("Failed to add background job: {err}");
                 } else {
                     if let Some(job) = manager.jobs.values().last() {
@@ -172,12 +307,34 @@ impl TishCommand {
         Ok(())
     }
 
+    // like nohup(1), prefer ./nohup.out and fall back to ~/nohup.out when the cwd isn't writable
+    fn open_nohup_output() -> Result<std::fs::File> {
+        let mut options = std::fs::OpenOptions::new();
+        options.create(true).append(true);
+
+        let (path, file) = match options.open("nohup.out") {
+            Ok(file) => ("nohup.out".to_string(), file),
+            Err(_) => {
+                let path = EnvManager::expand_tilde("~/nohup.out");
+                let file = options.open(&path).map_err(|err| anyhow!("nohup: failed to open '{path}': {err}"))?;
+                (path, file)
+            }
+        };
+
+        eprintln!("nohup: ignoring input and appending output to '{path}'");
+        Ok(file)
+    }
+
     async fn spawn_foreground_job(&self, signal_handler: &SignalHandler) -> Result<ExitCode> {
         let command = TishCommand::parse(&self.program);
 
         let mut cmd = tokio::process::Command::new(&command[0].program);
         cmd.args(&command[0].args).args(&self.args);
 
+        if self.heredoc.is_some() {
+            cmd.stdin(Stdio::piped());
+        }
+
         unsafe {
             cmd.pre_exec(|| {
                 if libc::setpgid(0, 0) != 0 {
@@ -195,6 +352,10 @@ impl TishCommand {
         let mut child = cmd.spawn()?;
         let pid = child.id().unwrap_or(0) as i32;
 
+        if let (Some(body), Some(mut stdin)) = (self.heredoc.clone(), child.stdin.take()) {
+            task::spawn(async move { stdin.write_all(body.as_bytes()).await });
+        }
+
         unsafe {
             std::thread::sleep(std::time::Duration::from_millis(1));
             if libc::tcsetpgrp(0, pid) != 0 {
@@ -225,9 +386,21 @@ impl TishCommand {
                 "TISH, version {}-release\n",
                 "These shell commands are defined internally. Type `help' to see this list.\n\n",
                 "  tish jobs           - List background jobs\n",
-                "  tish kill           - Kill a background job\n",
+                "  kill [-SIG] pid|%id - Signal a process or background job\n",
+                "  fg, bg [%id]        - Resume a job in the foreground or background\n",
+                "  disown [%id]        - Stop tracking a job so it survives exit\n",
                 "  tish pid            - Get current shell process id\n",
-                "  source              - Source a file for env\n",
+                "  source, . file      - Run a Lua file, or a file of shell commands in this shell\n",
+                "  pushd, popd         - Push or pop the directory stack\n",
+                "  dirs                - Show the directory stack\n",
+                "  tree [-a] [-L depth] - Show a directory tree\n",
+                "  cp, mv, rm [-rfi]   - Copy, move or remove files when config.use_tish_coreutils is set\n",
+                "  cat [-n] [file ...] - Print files or stdin, also behind config.use_tish_coreutils\n",
+                "  du [-hs] [-d depth] - Total directory sizes, also behind config.use_tish_coreutils\n",
+                "  history [-c] [n]    - Show the last n history entries, or clear them\n",
+                "  alias [-s|-g] [name=value] - Define or list command, suffix or global aliases\n",
+                "  unalias [-s|-g] [-a] name - Remove an alias, or all of them\n",
+                "  set [-+ex] [-+o name] - Toggle errexit and xtrace, or list them\n",
                 "  help, ?             - Show this message\n",
                 "  exit                - Exit TISH shell\n\n",
                 "  *.lua               - Execute Lua script\n",
@@ -238,14 +411,28 @@ impl TishCommand {
         Ok(ExitCode::SUCCESS)
     }
 
-    async fn handle_builtin_fg(&self) -> Result<ExitCode> {
-        let job_id = self.args.get(1).and_then(|s| s.parse::<usize>().ok());
+    // `fg 1` and `tish fg 1` both name the job in the first argument after the builtin
+    fn job_spec(&self) -> Option<&String> {
+        match self.program.as_str() {
+            "tish" => self.args.get(1),
+            _ => self.args.first(),
+        }
+    }
+
+    fn resume_job(&self, builtin: &str) -> Result<Option<(id_t, String)>> {
+        let mut jobs = crate::JOBS.try_lock().map_err(|_| anyhow!("{builtin}: unable to acquire jobs lock"))?;
 
-        let pid = match crate::JOBS.try_lock() {
-            Ok(mut jobs) => jobs.resume_job(job_id),
-            Err(_) => return Err(anyhow!("fg: unable to acquire jobs lock")),
+        let pid = match self.job_spec() {
+            Some(spec) => Some(jobs.resolve_spec(spec).map_err(|err| anyhow!("{builtin}: {err}"))?),
+            None => None,
         };
 
+        Ok(jobs.resume_job(pid).map(|job| (job.pid, format!("[{}] {} {}", job.id, job.command, job.args.join(" ")))))
+    }
+
+    async fn handle_builtin_fg(&self) -> Result<ExitCode> {
+        let pid = self.resume_job("fg")?.map(|(pid, _)| pid);
+
         match pid {
             Some(pid) => {
                 unsafe {
@@ -266,41 +453,315 @@ impl TishCommand {
         }
     }
 
-    async fn handle_builtin_kill(&self) -> Result<ExitCode> {
-        let pid = match self.args.get(0) {
-            Some(cmd) if cmd == "kill" => self.args.get(1).ok_or_else(|| anyhow!("kill: no process id specified"))?.parse()?,
-            Some(_) => return Err(anyhow!("kill: invalid command")),
-            None => return Err(anyhow!("kill: no command specified")),
+    fn handle_builtin_bg(&self) -> Result<ExitCode> {
+        let (pid, description) = self.resume_job("bg")?.ok_or_else(|| anyhow!("no current job"))?;
+
+        unsafe {
+            libc::kill(-(pid as i32), libc::SIGCONT);
+        }
+        println!("{} &", description.trim_end());
+
+        Ok(ExitCode::SUCCESS)
+    }
+
+    // `alias name=value` defines, a bare `alias name` prints one, and no arguments lists them all
+    fn handle_builtin_alias(&self) -> Result<ExitCode> {
+        let mut status = ExitCode::SUCCESS;
+
+        if self.args.is_empty() {
+            for (prefix, table) in [("", &crate::ALIASES), ("-s ", &crate::SUFFIX_ALIASES), ("-g ", &crate::GLOBAL_ALIASES)] {
+                Self::print_aliases(prefix, &table.lock().expect("Able to lock aliases"));
+            }
+            return Ok(status);
+        }
+
+        let (prefix, table, args) = Self::alias_table(&self.args);
+        let mut aliases = table.lock().expect("Able to lock aliases");
+
+        if args.is_empty() {
+            Self::print_aliases(prefix, &aliases);
+        }
+
+        for arg in args {
+            match arg.split_once('=') {
+                Some((name, _)) if name.is_empty() || name.contains(|c: char| c.is_whitespace() || matches!(c, '/' | '\'' | '"')) => {
+                    eprintln!("alias: invalid alias name '{name}'");
+                    status = ExitCode::FAILURE;
+                }
+                Some((name, value)) => {
+                    aliases.insert(name.to_string(), value.to_string());
+                }
+                None => match aliases.get(arg) {
+                    Some(value) => println!("alias {prefix}{arg}={}", Self::quote_alias(value)),
+                    None => {
+                        eprintln!("alias: {arg}: not found");
+                        status = ExitCode::FAILURE;
+                    }
+                },
+            }
+        }
+
+        Ok(status)
+    }
+
+    fn handle_builtin_unalias(&self) -> Result<ExitCode> {
+        let (_, table, args) = Self::alias_table(&self.args);
+        let mut aliases = table.lock().expect("Able to lock aliases");
+        let mut status = ExitCode::SUCCESS;
+
+        if args.is_empty() {
+            return Err(anyhow!("usage: unalias [-s|-g] [-a] name ..."));
+        }
+
+        for name in args {
+            if name == "-a" {
+                aliases.clear();
+            } else if aliases.remove(name).is_none() {
+                eprintln!("unalias: {name}: not found");
+                status = ExitCode::FAILURE;
+            }
+        }
+
+        Ok(status)
+    }
+
+    // `-s` picks the suffix aliases and `-g` the global ones, otherwise it's a plain command alias
+    fn alias_table(args: &[String]) -> (&'static str, &'static Mutex<HashMap<String, String>>, &[String]) {
+        match args.first().map(String::as_str) {
+            Some("-s") => ("-s ", &crate::SUFFIX_ALIASES, &args[1..]),
+            Some("-g") => ("-g ", &crate::GLOBAL_ALIASES, &args[1..]),
+            _ => ("", &crate::ALIASES, args),
+        }
+    }
+
+    fn print_aliases(prefix: &str, aliases: &HashMap<String, String>) {
+        let mut sorted: Vec<_> = aliases.iter().collect();
+        sorted.sort();
+
+        for (name, value) in sorted {
+            println!("alias {prefix}{name}={}", Self::quote_alias(value));
+        }
+    }
+
+    // single quoted so the printed line can be pasted back in, with embedded quotes as '\''
+    fn quote_alias(value: &str) -> String { format!("'{}'", value.replace('\'', "'\\''")) }
+
+    // entries are numbered from the start of the history file, so `history 5` keeps the real numbers
+    fn handle_builtin_history(&self) -> Result<ExitCode> {
+        let entries = AsyncLineReader::history();
+
+        let skip = match self.args.first().map(String::as_str) {
+            None => 0,
+            Some("-c") => {
+                AsyncLineReader::clear_history()?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            Some(count) => match count.parse::<usize>() {
+                Ok(count) => entries.len().saturating_sub(count),
+                Err(_) => return Err(anyhow!("history: {count}: numeric argument required")),
+            },
         };
 
-        let job_exists = match crate::JOBS.try_lock() {
-            Ok(jobs) => jobs.contains_pid(pid),
-            Err(_) => return Err(anyhow!("kill: unable to acquire lock, try again later")),
+        for (idx, entry) in entries.iter().enumerate().skip(skip) {
+            println!("{:>5}  {entry}", idx + 1);
+        }
+
+        Ok(ExitCode::SUCCESS)
+    }
+
+    // .tishrc and .tish_profile have no extension but are lua, anything else unknown runs as shell lines
+    async fn handle_builtin_source(&self, shell: &TishShell) -> Result<ExitCode> {
+        let path = Path::new(self.args.first().ok_or_else(|| anyhow!("Could not determine source file"))?);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        if name.ends_with(".lua") || name.ends_with(".tish") || matches!(name.as_ref(), ".tishrc" | ".tish_profile") {
+            return shell.lua.eval_file(path);
+        }
+
+        let script = std::fs::read_to_string(path).map_err(|err| anyhow!("source: {}: {err}", path.display()))?;
+        Ok(Box::pin(shell.run_lines(&script)).await)
+    }
+
+    // `-` turns an option on and `+` off, by letter (`set -ex`) or by name (`set +o xtrace`)
+    fn handle_builtin_set(&self, shell: &TishShell) -> Result<ExitCode> {
+        let mut args = self.args.iter();
+
+        if self.args.is_empty() {
+            shell.options.print();
+        }
+
+        while let Some(arg) = args.next() {
+            let (enable, flags) = match arg.split_at_checked(1) {
+                Some(("-", flags)) if !flags.is_empty() => (true, flags),
+                Some(("+", flags)) if !flags.is_empty() => (false, flags),
+                _ => return Err(anyhow!("set: {arg}: invalid option")),
+            };
+
+            if flags == "o" {
+                match args.next() {
+                    Some(name) => shell.options.flag(name).ok_or_else(|| anyhow!("set: {name}: invalid option name"))?.store(enable, Ordering::Relaxed),
+                    None => shell.options.print(),
+                }
+                continue;
+            }
+
+            for flag in flags.chars() {
+                let option = shell.options.flag(&flag.to_string()).ok_or_else(|| anyhow!("set: -{flag}: invalid option"))?;
+                option.store(enable, Ordering::Relaxed);
+            }
+        }
+
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_builtin_disown(&self) -> Result<ExitCode> {
+        let mut jobs = crate::JOBS.try_lock().map_err(|_| anyhow!("disown: unable to acquire jobs lock"))?;
+
+        let pid = match self.job_spec() {
+            Some(spec) => Some(jobs.resolve_spec(spec).map_err(|err| anyhow!("disown: {err}"))?),
+            None => None,
+        };
+
+        jobs.disown_job(pid)
+    }
+
+    // a plain pid is signalled like kill(1) whether or not it's a job, `%id` specs go through the job table
+    async fn handle_builtin_kill(args: &[String]) -> Result<ExitCode> {
+        let mut args = args.iter().peekable();
+
+        let signal = match args.next_if(|arg| arg.starts_with('-')) {
+            Some(arg) => parse_signal(&arg[1..])?,
+            None => Signal::SIGTERM,
         };
 
-        if !job_exists {
-            return Err(anyhow!("illegal process id: {}", pid));
+        if args.peek().is_none() {
+            return Err(anyhow!("kill: no process id specified"));
+        }
+
+        let mut jobs = crate::JOBS.try_lock().map_err(|_| anyhow!("kill: unable to acquire lock, try again later"))?;
+        for spec in args {
+            let pid = match spec.parse() {
+                Ok(pid) => pid,
+                Err(_) => jobs.resolve_spec(spec)?,
+            };
+            jobs.signal_job(pid, signal)?;
         }
 
-        match crate::JOBS.try_lock() {
-            Ok(mut jobs) => jobs.remove_job(pid).await?,
-            Err(_) => return Err(anyhow!("kill: unable to acquire lock, try again later")),
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_builtin_cd(&self, shell: &TishShell) -> Result<ExitCode> {
+        let target_dir = Self::resolve_directory(self.args.first().map(String::as_str))?;
+
+        let (target_dir, print_dir) = match target_dir.is_dir() {
+            true => (target_dir, self.args.first().is_some_and(|arg| arg == "-")),
+            false => match Self::search_cd_path(shell, &target_dir)? {
+                Some(found) => (found, true),
+                None => (target_dir, false),
+            },
         };
 
+        let current_dir = Self::change_directory(&target_dir)?;
+        if print_dir {
+            println!("{}", current_dir.display());
+        }
+
         Ok(ExitCode::SUCCESS)
     }
 
-    fn handle_builtin_cd(&self) -> Result<ExitCode> {
-        let target_dir = if self.args.is_empty() {
-            dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))?
-        } else {
-            PathBuf::from(&self.args[0])
+    fn search_cd_path(shell: &TishShell, target: &Path) -> Result<Option<PathBuf>> {
+        let target_str = target.to_string_lossy();
+        if target.is_absolute() || target_str.starts_with("./") || target_str.starts_with("../") {
+            return Ok(None);
+        }
+
+        let cd_path = match shell.lua.get_config_value::<Option<String>>("cd_path")? {
+            Some(cd_path) => cd_path,
+            None => env::var("CDPATH").unwrap_or_default(),
         };
 
-        env::set_current_dir(&target_dir).map_err(|_| anyhow!("cd: no such file or directory: {}", target_dir.display()))?;
+        for base in cd_path.split(':').filter(|base| !base.is_empty()) {
+            let candidate = Self::resolve_directory(Some(base))?.join(target);
+            if candidate.is_dir() {
+                return Ok(Some(candidate));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn handle_builtin_pushd(&self) -> Result<ExitCode> {
+        let current_dir = env::current_dir()?;
+
+        match self.args.first() {
+            Some(arg) => {
+                let target_dir = Self::resolve_directory(Some(arg))?;
+                Self::change_directory(&target_dir)?;
+                crate::DIR_STACK.lock().expect("Able to lock directory stack").push(current_dir);
+            }
+            None => {
+                let mut stack = crate::DIR_STACK.lock().expect("Able to lock directory stack");
+                let target_dir = stack.pop().ok_or_else(|| anyhow!("no other directory"))?;
+
+                if let Err(err) = Self::change_directory(&target_dir) {
+                    stack.push(target_dir);
+                    return Err(err);
+                }
+                stack.push(current_dir);
+            }
+        }
+
+        Self::handle_builtin_dirs()
+    }
+
+    fn handle_builtin_popd() -> Result<ExitCode> {
+        let mut stack = crate::DIR_STACK.lock().expect("Able to lock directory stack");
+        let target_dir = stack.pop().ok_or_else(|| anyhow!("directory stack empty"))?;
+
+        if let Err(err) = Self::change_directory(&target_dir) {
+            stack.push(target_dir);
+            return Err(err);
+        }
+
+        drop(stack);
+        Self::handle_builtin_dirs()
+    }
+
+    fn handle_builtin_dirs() -> Result<ExitCode> {
+        let stack = crate::DIR_STACK.lock().expect("Able to lock directory stack");
+        let current_dir = env::current_dir()?;
+
+        let entries: Vec<String> = std::iter::once(&current_dir)
+            .chain(stack.iter().rev())
+            .map(|dir| EnvManager::new(&dir.to_string_lossy()).contract_home())
+            .collect();
+
+        println!("{}", entries.join(" "));
         Ok(ExitCode::SUCCESS)
     }
 
+    fn resolve_directory(arg: Option<&str>) -> Result<PathBuf> {
+        match arg {
+            None => dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory")),
+            Some("-") => env::var("OLDPWD").map(PathBuf::from).map_err(|_| anyhow!("OLDPWD not set")),
+            Some(path) => Ok(PathBuf::from(EnvManager::expand_tilde(path))),
+        }
+    }
+
+    fn change_directory(target_dir: &Path) -> Result<PathBuf> {
+        let previous_dir = env::current_dir().ok();
+
+        env::set_current_dir(target_dir).map_err(|_| anyhow!("cd: no such file or directory: {}", target_dir.display()))?;
+        let current_dir = env::current_dir().unwrap_or_else(|_| target_dir.to_path_buf());
+
+        if let Some(previous_dir) = previous_dir {
+            env_set_sync!("OLDPWD" => previous_dir);
+        }
+        env_set_sync!("PWD" => &current_dir);
+
+        Ok(current_dir)
+    }
+
     fn parse_single_command(mut tokenizer: Tokenizer) -> Self {
         let tokens = if tokenizer.has_redirection() { tokenizer.args_before_redirection() } else { tokenizer.get_args() };
 
@@ -312,6 +773,7 @@ impl TishCommand {
                 pipe_to: None,
                 redirect_in: None,
                 redirect_out: None,
+                heredoc: None,
             };
         }
 
@@ -352,6 +814,57 @@ impl TishCommand {
             pipe_to: None,
             redirect_in,
             redirect_out,
+            heredoc: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        let commands = TishCommand::parse(line);
+        let command = commands.first().expect("line has a command");
+        std::iter::once(command.program.clone()).chain(command.args.iter().cloned()).collect()
+    }
+
+    #[test]
+    fn comment_is_stripped() {
+        assert_eq!(words("ls # list files"), ["ls"]);
+        assert!(TishCommand::parse("# only a comment").is_empty());
+    }
+
+    #[test]
+    fn quoted_or_mid_word_hash_is_kept() {
+        assert_eq!(words("echo '# not a comment'"), ["echo", "# not a comment"]);
+        assert_eq!(words("echo \"a # b\""), ["echo", "a # b"]);
+        assert_eq!(words("curl https://example.com/#fragment"), ["curl", "https://example.com/#fragment"]);
+    }
+
+    #[test]
+    fn alias_resolves_after_and() {
+        crate::ALIASES.lock().expect("Able to lock aliases").insert("tish_ll".to_string(), "ls -la".to_string());
+        crate::ALIASES.lock().expect("Able to lock aliases").insert("tish_both".to_string(), "true && tish_ll".to_string());
+
+        let commands = TishCommand::parse("true && tish_ll src");
+        assert_eq!((commands[1].program.as_str(), commands[1].args.as_slice()), ("ls", ["-la".to_string(), "src".to_string()].as_slice()));
+
+        let commands = TishCommand::parse("tish_both");
+        assert_eq!((commands[0].program.as_str(), commands[1].program.as_str()), ("true", "ls"));
+    }
+
+    #[test]
+    fn escapes_and_quotes_survive_expansion() {
+        assert_eq!(words(r"echo a\ b"), ["echo", "a b"]);
+        assert_eq!(words(r#"echo "a \"b\"""#), ["echo", "a \"b\""]);
+        assert_eq!(words(r"echo 'it\'s'"), ["echo", r"it\s"]);
+        assert_eq!(words(r"echo 'it'\''s'"), ["echo", "it's"]);
+    }
+
+    #[test]
+    fn variable_with_spaces_splits_into_words() {
+        std::env::set_var("TISH_TEST_SPACED", "x  y");
+        assert_eq!(words(r"echo a\ b $TISH_TEST_SPACED"), ["echo", "a b", "x", "y"]);
+    }
+}
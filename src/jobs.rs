@@ -1,6 +1,7 @@
 use std::{
     collections::HashMap,
-    process::{ExitCode, Stdio},
+    process::ExitCode,
+    str::FromStr,
     sync::atomic::{AtomicUsize, Ordering},
 };
 
@@ -12,7 +13,23 @@ use nix::{
 
 use anyhow::{anyhow, Result};
 use libc::id_t;
-use tokio::process::Command;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+// accepts `9`, `KILL` or `SIGKILL`, case insensitive
+pub fn parse_signal(name: &str) -> Result<Signal> {
+    let signal = match name.parse::<i32>() {
+        Ok(number) => Signal::try_from(number).ok(),
+        Err(_) => {
+            let name = name.to_uppercase();
+            match name.starts_with("SIG") {
+                true => Signal::from_str(&name).ok(),
+                false => Signal::from_str(&format!("SIG{name}")).ok(),
+            }
+        }
+    };
+
+    signal.ok_or_else(|| anyhow!("kill: {name}: invalid signal specification"))
+}
 
 #[derive(Debug)]
 pub enum JobStatus {
@@ -33,6 +50,7 @@ pub struct Job {
 pub struct JobManager {
     pub jobs: HashMap<id_t, Job>,
     job_counter: AtomicUsize,
+    recent: Vec<usize>,
 }
 
 impl JobManager {
@@ -40,14 +58,51 @@ impl JobManager {
         Self {
             jobs: HashMap::new(),
             job_counter: AtomicUsize::new(1),
+            recent: Vec::new(),
+        }
+    }
+
+    // the last entry is the current job (%+), the one before it the previous job (%-)
+    fn mark_current(&mut self, job_id: usize) {
+        self.recent.retain(|&id| id != job_id);
+        self.recent.push(job_id);
+    }
+
+    fn current_id(&self) -> Option<usize> { self.recent.last().copied() }
+
+    fn previous_id(&self) -> Option<usize> { self.recent.iter().rev().nth(1).copied() }
+
+    fn marker(&self, job_id: usize) -> char {
+        match Some(job_id) {
+            id if id == self.current_id() => '+',
+            id if id == self.previous_id() => '-',
+            _ => ' ',
         }
     }
 
-    pub fn add_job(&mut self, handle: &mut Command, command: String, args: Vec<String>) -> Result<ExitCode> {
-        handle.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    pub fn resolve_spec(&self, spec: &str) -> Result<id_t> {
+        let job_id = match spec.strip_prefix('%') {
+            Some("" | "%" | "+") => self.current_id().ok_or_else(|| anyhow!("{spec}: no current job"))?,
+            Some("-") => self.previous_id().ok_or_else(|| anyhow!("{spec}: no previous job"))?,
+            Some(id) => id.parse().map_err(|_| anyhow!("{spec}: invalid job spec"))?,
+            None => {
+                let pid = spec.parse().map_err(|_| anyhow!("{spec}: arguments must be process or job IDs"))?;
+                return match self.contains_pid(pid) {
+                    true => Ok(pid),
+                    false => Err(anyhow!("illegal process id: {pid}")),
+                };
+            }
+        };
 
+        self.get_job_by_id(job_id).map(|job| job.pid).ok_or_else(|| anyhow!("{spec}: no such job"))
+    }
+
+    // a heredoc body is written to the job's stdin in the background, otherwise stdin is closed
+    pub fn add_job(&mut self, handle: &mut Command, command: String, args: Vec<String>, heredoc: Option<String>) -> Result<ExitCode> {
         let mut child = handle.spawn()?;
-        child.stdin.take();
+        if let (Some(body), Some(mut stdin)) = (heredoc, child.stdin.take()) {
+            tokio::spawn(async move { stdin.write_all(body.as_bytes()).await });
+        }
         child.stdout.take();
         child.stderr.take();
 
@@ -64,6 +119,7 @@ impl JobManager {
                 status: JobStatus::Running,
             },
         );
+        self.mark_current(job_id);
 
         Ok(ExitCode::SUCCESS)
     }
@@ -74,6 +130,15 @@ impl JobManager {
 
     pub fn get_last_suspended(&self) -> Option<&Job> { self.jobs.values().filter(|job| matches!(job.status, JobStatus::Suspended)).max_by_key(|job| job.id) }
 
+    // jobs that finished since the last `jobs` listing are still tracked, so check the process too
+    pub fn active_count(&self) -> usize {
+        self.jobs
+            .values()
+            .filter(|job| matches!(job.status, JobStatus::Running | JobStatus::Suspended))
+            .filter(|job| i32::try_from(job.pid).is_ok_and(|pid| kill(Pid::from_raw(pid), None).is_ok()))
+            .count()
+    }
+
     pub async fn remove_job(&mut self, pid: id_t) -> Result<ExitCode> {
         let job = self.jobs.get_mut(&pid).ok_or_else(|| anyhow!("kill: {}: No such process", pid))?;
         let i32_pid: i32 = pid.try_into().map_err(|_| anyhow!("PID too large"))?;
@@ -89,11 +154,76 @@ impl JobManager {
         }
 
         job.status = JobStatus::Completed(0);
-        self.jobs.remove(&pid);
+        self.forget_job(pid);
 
         Ok(ExitCode::SUCCESS)
     }
 
+    pub fn signal_job(&mut self, pid: id_t, signal: Signal) -> Result<ExitCode> {
+        let i32_pid: i32 = pid.try_into().map_err(|_| anyhow!("PID too large"))?;
+
+        match kill(Pid::from_raw(i32_pid), signal) {
+            Ok(_) => (),
+            Err(Errno::ESRCH) => {
+                self.forget_job(pid);
+                return Err(anyhow!("kill: {}: No such process", pid));
+            }
+            Err(err) => return Err(anyhow!("kill: {}: {}", pid, err)),
+        }
+
+        match signal {
+            Signal::SIGKILL | Signal::SIGTERM => self.forget_job(pid),
+            Signal::SIGSTOP | Signal::SIGTSTP => {
+                if let Some(job) = self.jobs.get_mut(&pid) {
+                    job.status = JobStatus::Suspended;
+                }
+            }
+            Signal::SIGCONT => {
+                if let Some(job) = self.jobs.get_mut(&pid) {
+                    job.status = JobStatus::Running;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(ExitCode::SUCCESS)
+    }
+
+    // background jobs aren't group leaders, so fall back to the pid when the group doesn't exist
+    pub fn hangup_all(&mut self) {
+        for (pid, job) in self.jobs.drain() {
+            let Ok(pid) = i32::try_from(pid) else { continue };
+
+            for signal in [Signal::SIGHUP, Signal::SIGCONT] {
+                if kill(Pid::from_raw(-pid), signal).is_err() {
+                    let _ = kill(Pid::from_raw(pid), signal);
+                }
+
+                if !matches!(job.status, JobStatus::Suspended) {
+                    break;
+                }
+            }
+        }
+        self.recent.clear();
+    }
+
+    // stops tracking the job without signalling it, so it outlives the shell
+    pub fn disown_job(&mut self, pid: Option<id_t>) -> Result<ExitCode> {
+        let pid = match pid {
+            Some(pid) => pid,
+            None => self.current_id().and_then(|id| self.get_job_by_id(id)).ok_or_else(|| anyhow!("no current job"))?.pid,
+        };
+
+        self.forget_job(pid);
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn forget_job(&mut self, pid: id_t) {
+        if let Some(job) = self.jobs.remove(&pid) {
+            self.recent.retain(|&id| id != job.id);
+        }
+    }
+
     pub fn suspend_job(&mut self, pid: id_t, command: &String, args: &Vec<String>) {
         let job_id = self.job_counter.fetch_add(1, Ordering::SeqCst);
 
@@ -112,12 +242,15 @@ impl JobManager {
             job.status = JobStatus::Suspended;
             println!("\n[{}] tish: suspended {} {}", job.id, job.command, job.args.join(" "));
         }
+        self.mark_current(job_id);
     }
 
     pub async fn list_jobs(&mut self) -> Result<ExitCode> {
         let mut completed_pids = Vec::new();
+        let mut jobs: Vec<&Job> = self.jobs.values().collect();
+        jobs.sort_by_key(|job| job.id);
 
-        for job in self.jobs.values() {
+        for job in jobs {
             let i32_pid: i32 = job.pid.try_into().map_err(|_| anyhow!("PID too large"))?;
             let is_running = kill(Pid::from_raw(i32_pid), None).is_ok();
 
@@ -142,7 +275,7 @@ impl JobManager {
                 }
             };
 
-            println!("[{}] {} {} {}", job.id, status_str, job.command, job.args.join(" "));
+            println!("[{}]{} {:<10} {} {}", job.id, self.marker(job.id), status_str, job.command, job.args.join(" "));
         }
 
         for pid in completed_pids {
@@ -152,16 +285,17 @@ impl JobManager {
         Ok(ExitCode::SUCCESS)
     }
 
-    pub fn resume_job(&mut self, job_id: Option<usize>) -> Option<id_t> {
-        let job = match job_id {
-            Some(id) => self.jobs.values().find(|j| j.id == id),
-            None => self.get_last_suspended(),
-        }?;
+    pub fn resume_job(&mut self, pid: Option<id_t>) -> Option<&Job> {
+        let pid = match pid {
+            Some(pid) => pid,
+            None => self.current_id().and_then(|id| self.get_job_by_id(id)).or_else(|| self.get_last_suspended())?.pid,
+        };
 
-        let pid = job.pid;
-        if let Some(job) = self.jobs.get_mut(&pid) {
-            job.status = JobStatus::Running;
-        }
-        Some(pid)
+        let job_id = self.jobs.get(&pid)?.id;
+        self.mark_current(job_id);
+
+        let job = self.jobs.get_mut(&pid)?;
+        job.status = JobStatus::Running;
+        Some(job)
     }
 }
@@ -1,6 +1,6 @@
 mod modules;
 
-use crate::prelude::*;
+use crate::{os::env::EnvManager, prelude::*};
 use libc::pid_t;
 use mlua::prelude::*;
 
@@ -8,7 +8,7 @@ use std::{
     env::{self, consts},
     fs::{self, File},
     path::{Path, PathBuf},
-    process::{Command, ExitCode},
+    process::{Child, Command, ExitCode},
     time::{SystemTime, UNIX_EPOCH},
 };
 
@@ -20,6 +20,10 @@ struct FileWrapper {
     file: File,
 }
 
+struct ChildWrapper {
+    child: Child,
+}
+
 struct LuaFile;
 
 struct LuaEnv;
@@ -28,6 +32,19 @@ struct LuaAlias;
 
 struct LuaSystem;
 
+impl LuaProcess {
+    fn process_info(lua: &Lua, process: &sysinfo::Process) -> LuaResult<LuaTable> {
+        let info = lua.create_table()?;
+        info.set("pid", process.pid().as_u32() as i64)?;
+        info.set("ppid", process.parent().map(|pid| pid.as_u32() as i64))?;
+        info.set("name", process.name())?;
+        info.set("status", process.status().to_string())?;
+        info.set("memory", process.memory() as f64)?;
+        info.set("cpu_usage", process.cpu_usage() as f64)?;
+        Ok(info)
+    }
+}
+
 impl LuaUserData for LuaProcess {
     fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
         methods.add_function("list", |lua, ()| {
@@ -37,24 +54,42 @@ impl LuaUserData for LuaProcess {
             let processes = sys.processes();
             let process_table = lua.create_table()?;
 
-            for (i, (pid, process)) in processes.iter().enumerate() {
-                let process_info = lua.create_table()?;
-                process_info.set("pid", pid.as_u32() as i64)?;
-                process_info.set("name", process.name())?;
-                process_info.set("memory", process.memory() as f64)?;
-                process_info.set("cpu_usage", process.cpu_usage() as f64)?;
-                process_table.set(i + 1, process_info)?;
+            for (i, process) in processes.values().enumerate() {
+                process_table.set(i + 1, Self::process_info(lua, process)?)?;
             }
 
             Ok(process_table)
         });
 
+        methods.add_function("info", |lua, pid: u32| {
+            let pid = sysinfo::Pid::from_u32(pid);
+            let mut sys = sysinfo::System::new();
+            sys.refresh_processes(sysinfo::ProcessesToUpdate::Some(&[pid]), true);
+
+            sys.process(pid).map(|process| Self::process_info(lua, process)).transpose()
+        });
+
+        // substring match on the process name like pgrep, sorted so the oldest pids come first
+        methods.add_function("find", |_, name: String| {
+            let mut sys = sysinfo::System::new();
+            sys.refresh_processes(sysinfo::ProcessesToUpdate::All, true);
+
+            let mut pids: Vec<u32> = sys.processes_by_name(name.as_ref()).map(|process| process.pid().as_u32()).collect();
+            pids.sort_unstable();
+            Ok(pids)
+        });
+
         methods.add_function("kill", |_, pid: pid_t| {
             use nix::sys::signal::{kill, Signal};
             use nix::unistd::Pid;
             Ok(kill(Pid::from_raw(pid), Signal::SIGTERM).map_err(LuaError::external)?)
         });
 
+        methods.add_function("spawn", |_, (cmd, args): (String, Option<Vec<String>>)| {
+            let child = Command::new(cmd).args(args.unwrap_or_default()).spawn()?;
+            Ok(ChildWrapper { child })
+        });
+
         methods.add_function("exit", |lua: &Lua, code: Option<i32>| -> LuaResult<()> {
             let code = code.unwrap_or(0);
             lua.set_named_registry_value("__tish_exit_code", code)?;
@@ -67,7 +102,10 @@ impl LuaUserData for LuaProcess {
         fields.add_field_function_get("cwd", |_, _| Ok(env::current_dir()?));
         fields.add_field_method_get("pid", |_, process| Ok(process.pid));
         fields.add_field_function_get("ppid", |_, _| Ok(nix::unistd::getppid().as_raw()));
-        fields.add_field_function_get("euid", |_, _| Ok(nix::unistd::getpgrp().as_raw()));
+        fields.add_field_function_get("uid", |_, _| Ok(unsafe { libc::getuid() }));
+        fields.add_field_function_get("euid", |_, _| Ok(crate::os::user::effective_uid()));
+        fields.add_field_function_get("gid", |_, _| Ok(unsafe { libc::getgid() }));
+        fields.add_field_function_get("egid", |_, _| Ok(unsafe { libc::getegid() }));
     }
 }
 
@@ -107,6 +145,13 @@ impl LuaUserData for LuaFile {
             Ok(FileWrapper { file })
         });
 
+        methods.add_function("read_file", |_, path: String| Ok(fs::read_to_string(&path)?));
+        methods.add_function("write_file", |_, (path, contents): (String, String)| Ok(fs::write(&path, contents)?));
+
+        methods.add_function("exists", |_, path: String| Ok(Path::new(&path).exists()));
+        methods.add_function("is_dir", |_, path: String| Ok(Path::new(&path).is_dir()));
+        methods.add_function("is_file", |_, path: String| Ok(Path::new(&path).is_file()));
+
         methods.add_function("create_dir", |_, path: String| Ok(fs::create_dir(&path)?));
         methods.add_function("create_dir_all", |_, path: String| Ok(fs::create_dir_all(&path)?));
         methods.add_function("remove_file", |_, path: String| Ok(fs::remove_file(&path)?));
@@ -137,6 +182,24 @@ impl LuaUserData for LuaFile {
     }
 }
 
+impl LuaUserData for ChildWrapper {
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        use std::os::unix::process::ExitStatusExt;
+
+        // lua runs on a runtime worker, so let tokio move other tasks off it while the child runs
+        methods.add_method_mut("wait", |_, this, ()| {
+            let status = tokio::task::block_in_place(|| this.child.wait())?;
+            Ok(status.code().or_else(|| status.signal().map(|signal| 128 + signal)))
+        });
+
+        methods.add_method_mut("kill", |_, this, ()| Ok(this.child.kill()?));
+    }
+
+    fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("pid", |_, this| Ok(this.child.id()));
+    }
+}
+
 impl LuaUserData for FileWrapper {
     fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
         use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
@@ -198,9 +261,43 @@ impl LuaUserData for FileWrapper {
     }
 }
 
+impl LuaEnv {
+    fn vars(lua: &Lua) -> LuaResult<LuaTable> {
+        lua.create_table_from(env::vars_os().map(|(key, value)| (key.to_string_lossy().into_owned(), value.to_string_lossy().into_owned())))
+    }
+
+    // a directory already on PATH stays where it is instead of being added twice
+    fn path_insert(dir: &str, prepend: bool) -> LuaResult<()> {
+        let dir = PathBuf::from(EnvManager::expand_tilde(dir));
+        let mut paths: Vec<PathBuf> = env::var_os("PATH").map(|path| env::split_paths(&path).collect()).unwrap_or_default();
+
+        if paths.contains(&dir) {
+            return Ok(());
+        }
+
+        match prepend {
+            true => paths.insert(0, dir),
+            false => paths.push(dir),
+        }
+
+        let path = env::join_paths(paths).map_err(LuaError::external)?;
+        env_set_sync!("PATH" => path);
+        Ok(())
+    }
+}
+
 impl LuaUserData for LuaEnv {
     fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
         methods.add_function("unset", |_, name: String| Ok(env::remove_var(name)));
+        methods.add_function("list", |lua, ()| LuaEnv::vars(lua));
+        methods.add_function("path_prepend", |_, dir: String| LuaEnv::path_insert(&dir, true));
+        methods.add_function("path_append", |_, dir: String| LuaEnv::path_insert(&dir, false));
+
+        methods.add_meta_function(LuaMetaMethod::Pairs, |lua, _: LuaAnyUserData| {
+            let next: LuaFunction = lua.globals().get("next")?;
+            let vars = LuaEnv::vars(lua)?;
+            Ok((next, vars, LuaNil))
+        });
         methods.add_meta_method(LuaMetaMethod::Index, |_, _, key: String| Ok(env::var(key).ok()));
         methods.add_meta_method_mut(LuaMetaMethod::NewIndex, |_, _, (key, value): (String, String)| Ok(env_set_sync!(key => value)));
     }
@@ -225,6 +322,16 @@ impl LuaUserData for LuaSystem {
     fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
         methods.add_function("uptime", |_, ()| Ok(sysinfo::System::uptime()));
 
+        methods.add_function("term_size", |lua, ()| match crate::os::size::dimensions() {
+            Some((cols, rows)) => {
+                let size = lua.create_table()?;
+                size.set("cols", cols)?;
+                size.set("rows", rows)?;
+                Ok(Some(size))
+            }
+            None => Ok(None),
+        });
+
         methods.add_function("eval_to_str", |_, command: String| {
             let mut parts = command.split_whitespace();
 
@@ -235,6 +342,36 @@ impl LuaUserData for LuaSystem {
             String::from_utf8(output.stdout).map_err(LuaError::external)
         });
 
+        methods.add_function("run", |lua, command: String| {
+            let output = Command::new("sh").arg("-c").arg(&command).output().map_err(LuaError::external)?;
+            let result = lua.create_table()?;
+
+            result.set("code", output.status.code())?;
+            result.set("stdout", String::from_utf8_lossy(&output.stdout).into_owned())?;
+            result.set("stderr", String::from_utf8_lossy(&output.stderr).into_owned())?;
+
+            Ok(result)
+        });
+
+        // output is discarded so it can't land on the prompt, and a thread reaps the child once it exits
+        methods.add_function("exec_background", |_, command: String| {
+            use std::{os::unix::process::CommandExt, process::Stdio};
+
+            let mut child = Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .process_group(0)
+                .spawn()
+                .map_err(LuaError::external)?;
+
+            let pid = child.id();
+            std::thread::spawn(move || child.wait());
+            Ok(pid)
+        });
+
         methods.add_function("timestamp", |_, ()| {
             let start = SystemTime::now();
             let since_epoch = start.duration_since(UNIX_EPOCH).map_err(LuaError::external)?;
@@ -286,28 +423,143 @@ impl Drop for LuaState {
     }
 }
 
+// keys with a nil default still need a type, so validation can't rely on the defaults alone
+const CONFIG_TYPES: &[(&str, &str)] = &[
+    ("lua_path", "string"),
+    ("lua_cpath", "string"),
+    ("history_size", "integer"),
+    ("auto_cd", "boolean"),
+    ("lua_prefix", "string"),
+    ("huponexit", "boolean"),
+    ("cd_path", "string"),
+    ("use_tish_ls", "boolean"),
+    ("use_tish_coreutils", "boolean"),
+    ("show_hidden", "boolean"),
+    ("truecolor", "boolean"),
+    ("prompt_cmd_timeout", "integer"),
+    ("path_condense_limit", "integer"),
+    ("path_condense_keep", "integer"),
+    ("prompt", "string"),
+    ("rprompt", "string"),
+    ("transient_prompt", "string"),
+    ("aliases", "table"),
+    ("completion_ignore_case", "boolean"),
+    ("colors", "table"),
+    ("ls_colors", "table"),
+    ("use_icons", "boolean"),
+];
+
 impl LuaState {
     pub fn new() -> anyhow::Result<Self> {
         let lua = Lua::new();
+        let cfg_table = Self::default_config(&lua)?;
+        Self::merge_config_aliases(&lua, &cfg_table)?;
+
+        let config = Some(lua.create_registry_value(cfg_table)?);
+        let state = Self { lua, config };
+
+        if let Some(ref registry) = state.config {
+            state.lua.globals().set("config", registry)?;
+        }
+
+        state.setup_runtime()?;
+        Ok(state)
+    }
+
+    fn default_config(lua: &Lua) -> LuaResult<LuaTable> {
         let cfg_table = lua.create_table()?;
 
         cfg_table.set("lua_path", LuaNil)?;
         cfg_table.set("lua_cpath", LuaNil)?;
         cfg_table.set("history_size", 500)?;
         cfg_table.set("auto_cd", true)?;
+        cfg_table.set("lua_prefix", LuaNil)?;
+        cfg_table.set("huponexit", true)?;
+        cfg_table.set("cd_path", LuaNil)?;
         cfg_table.set("use_tish_ls", false)?;
+        cfg_table.set("use_tish_coreutils", false)?;
         cfg_table.set("show_hidden", false)?;
+        cfg_table.set("truecolor", LuaNil)?;
+        cfg_table.set("prompt_cmd_timeout", 500)?;
+        cfg_table.set("path_condense_limit", 40)?;
+        cfg_table.set("path_condense_keep", 1)?;
         cfg_table.set("prompt", "{user}@{host} {path} {prompt}{' '}")?;
+        cfg_table.set("rprompt", LuaNil)?;
+        cfg_table.set("transient_prompt", LuaNil)?;
+        cfg_table.set("aliases", LuaNil)?;
+        cfg_table.set("completion_ignore_case", false)?;
+        cfg_table.set("colors", LuaNil)?;
+        cfg_table.set("ls_colors", LuaNil)?;
+        cfg_table.set("use_icons", true)?;
+
+        Ok(cfg_table)
+    }
 
-        let config = Some(lua.create_registry_value(cfg_table)?);
-        let state = Self { lua, config };
+    // `config.aliases = {...}` merges into the alias table when it's assigned, so it and `alias.name = ...`
+    // follow declaration order, and reading it back gives the same view as `alias`
+    fn merge_config_aliases(lua: &Lua, cfg_table: &LuaTable) -> LuaResult<()> {
+        let meta = lua.create_table()?;
+
+        meta.set(
+            "__index",
+            lua.create_function(|_, (_, key): (LuaTable, LuaValue)| Ok(key.as_str().is_some_and(|key| key == "aliases").then_some(LuaAlias)))?,
+        )?;
+
+        meta.set(
+            "__newindex",
+            lua.create_function(|_, (config, key, value): (LuaTable, LuaValue, LuaValue)| match (key.as_str().as_deref(), value) {
+                (Some("aliases"), LuaValue::Table(aliases)) => {
+                    let mut alias = crate::ALIASES.lock().expect("Able to lock aliases");
+                    for pair in aliases.pairs::<String, String>() {
+                        let (name, value) = pair?;
+                        alias.insert(name, value);
+                    }
+                    Ok(())
+                }
+                (_, value) => config.raw_set(key, value),
+            })?,
+        )?;
 
-        if let Some(ref registry) = state.config {
-            state.lua.globals().set("config", registry)?;
+        cfg_table.set_metatable(Some(meta));
+        Ok(())
+    }
+
+    // a typo or a wrongly typed value in .tishrc only warns, and the latter falls back to the default
+    fn validate_config(&self) -> anyhow::Result<()> {
+        let Some(ref registry_key) = self.config else {
+            anyhow::bail!("Config not initialized")
+        };
+
+        let config: LuaTable = self.lua.registry_value(registry_key)?;
+        let mut invalid = Vec::new();
+
+        for pair in config.pairs::<LuaValue, LuaValue>() {
+            let (key, value) = pair?;
+            let name = key.to_string()?;
+
+            match CONFIG_TYPES.iter().find(|(known, _)| *known == name) {
+                None => eprintln!("tish: config.{name} is not a known option"),
+                Some((_, expected)) if !Self::has_type(&value, expected) => {
+                    eprintln!("tish: config.{name} should be {expected}, not {}, using the default", value.type_name());
+                    invalid.push(name);
+                }
+                Some(_) => {}
+            }
         }
 
-        state.setup_runtime()?;
-        Ok(state)
+        let defaults = Self::default_config(&self.lua)?;
+        for name in invalid {
+            config.set(name.as_str(), defaults.get::<LuaValue>(name.as_str())?)?;
+        }
+
+        Ok(())
+    }
+
+    fn has_type(value: &LuaValue, expected: &str) -> bool {
+        match (value, expected) {
+            (LuaValue::Number(number), "integer") => number.fract() == 0.0,
+            (value, expected) => value.type_name() == expected,
+        }
     }
 
     pub fn setup_runtime(&self) -> anyhow::Result<std::process::ExitCode> {
@@ -320,6 +572,74 @@ impl LuaState {
         globals.set("env", LuaEnv)?;
         globals.set("sys", LuaSystem)?;
 
+        self.lua.set_named_registry_value("__tish_commands", self.lua.create_table()?)?;
+
+        define! {
+            self.lua, tish, "command",
+            |lua, (name, func): (String, LuaFunction)| {
+                let commands: LuaTable = lua.named_registry_value("__tish_commands")?;
+                commands.set(name.as_str(), func)?;
+                crate::LUA_FN.insert(name);
+                Ok(())
+            }
+        }
+
+        define! {
+            self.lua, tish, "history",
+            |_, count: Option<usize>| {
+                let entries = crate::readline::AsyncLineReader::history();
+                let skip = count.map_or(0, |count| entries.len().saturating_sub(count));
+                Ok(entries.into_iter().skip(skip).collect::<Vec<_>>())
+            }
+        }
+
+        define! {
+            self.lua, tish, "keybind",
+            |_, (sequence, action): (String, LuaValue)| {
+                use crate::readline::{AsyncLineReader, KeyAction};
+
+                let action = match action {
+                    LuaValue::String(name) => KeyAction::Command(name.to_str()?.to_string()),
+                    LuaValue::Table(table) => KeyAction::Insert(table.get("insert")?),
+                    _ => return Err(LuaError::external("keybind action must be a command name or { insert = text }")),
+                };
+
+                AsyncLineReader::bind_key(&sequence, action).map_err(LuaError::external)
+            }
+        }
+
+        define! {
+            self.lua, tish, "complete",
+            |_, (command, spec): (String, LuaValue)| {
+                use crate::shell::completion::CompletionSpec;
+
+                let spec = match spec {
+                    LuaValue::Function(func) => CompletionSpec::Lua(func),
+                    LuaValue::Table(table) => CompletionSpec::Words(table.sequence_values::<String>().collect::<LuaResult<_>>()?),
+                    _ => return Err(LuaError::external("completion spec must be a function or a list of words")),
+                };
+
+                crate::COMPLETIONS.lock().expect("Able to lock completions").insert(command, spec);
+                Ok(())
+            }
+        }
+
+        define! {
+            self.lua, tish, "tree",
+            |_, args: mlua::Variadic<String>| {
+                let code = crate::cmd::tree::run(&args).map_err(LuaError::external)?;
+                Ok(if code == ExitCode::SUCCESS { 0 } else { 1 })
+            }
+        }
+
+        define! {
+            self.lua, tish, "history_add",
+            |_, line: String| {
+                crate::HISTORY_QUEUE.lock().expect("Able to lock history queue").push(line);
+                Ok(())
+            }
+        }
+
         globals.set("process", process)?;
         globals.set("tish", tish)?;
 
@@ -328,6 +648,22 @@ impl LuaState {
             |_, value: LuaValue| Ok(println!("{value:#?}"))
         }
 
+        define! {
+            self.lua, globals, "glob",
+            |_, pattern: String| {
+                let pattern = EnvManager::expand_tilde(&pattern);
+
+                let mut paths: Vec<String> = glob::glob(&pattern)
+                    .map_err(LuaError::external)?
+                    .filter_map(|entry| entry.ok())
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .collect();
+
+                paths.sort();
+                Ok(paths)
+            }
+        }
+
         Ok(ExitCode::SUCCESS)
     }
 
@@ -363,6 +699,8 @@ impl LuaState {
         self.eval(&code)?;
 
         if path.ends_with(".tishrc") {
+            self.validate_config()?;
+
             if let Ok(globals) = self.lua.globals().get::<LuaTable>("_G") {
                 crate::LUA_FN.clear();
                 for pair in globals.pairs::<String, LuaValue>() {
@@ -374,11 +712,38 @@ impl LuaState {
                     }
                 }
             }
+
+            let commands: LuaTable = self.lua.named_registry_value("__tish_commands")?;
+            for name in commands.pairs::<String, LuaFunction>().filter_map(Result::ok).map(|(name, _)| name) {
+                crate::LUA_FN.insert(name);
+            }
         }
 
         Ok(ExitCode::SUCCESS)
     }
 
+    pub fn call_command(&self, name: &str, args: &[String]) -> anyhow::Result<Option<ExitCode>> {
+        let commands: LuaTable = self.lua.named_registry_value("__tish_commands")?;
+
+        let Some(func) = commands.get::<Option<LuaFunction>>(name)? else {
+            return Ok(None);
+        };
+
+        let code = match func.call::<LuaValue>(args.to_vec()) {
+            Ok(LuaValue::Integer(code)) => ExitCode::from(code as u8),
+            Ok(LuaValue::Number(code)) => ExitCode::from(code as u8),
+            Ok(LuaValue::Boolean(false)) => ExitCode::FAILURE,
+            Ok(_) => ExitCode::SUCCESS,
+            Err(LuaError::ExternalError(err)) if err.to_string() == "__tish_exit" => {
+                let code = self.lua.named_registry_value::<i32>("__tish_exit_code")?;
+                ExitCode::from(code as u8)
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Some(code))
+    }
+
     pub fn get_config_value<T: FromLua>(&self, key: &str) -> anyhow::Result<T> {
         if let Some(ref registry_key) = self.config {
             let config: LuaTable = self.lua.registry_value(registry_key)?;
@@ -49,17 +49,44 @@ macro_rules! dotfile {
 macro_rules! argument {
     (
         args: $args:expr,
-        options: { $( $opt:ident => $set:expr ),* },
+        options: { $( $opt:tt => $set:expr ),* },
+        command: $command:expr,
+        on_invalid: $on_invalid:expr
+    ) => {
+        $crate::argument! {
+            args: $args,
+            options: { $( $opt => $set ),* },
+            long: {},
+            command: $command,
+            on_invalid: $on_invalid
+        }
+    };
+    (
+        args: $args:expr,
+        options: { $( $opt:tt => $set:expr ),* },
+        long: { $( $long:literal ($value:ident) => $long_set:expr ),* },
         command: $command:expr,
         on_invalid: $on_invalid:expr
     ) => {
         for arg in $args {
-            if arg.starts_with('-') && arg.len() > 1 {
+            if let Some(long) = arg.strip_prefix("--").filter(|long| !long.is_empty()) {
+                let (name, _value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (long, None),
+                };
+                match name {
+                    $( $long => { let $value: Option<&str> = _value; $long_set } )*
+                    _ => {
+                        $on_invalid(format!("--{name}"));
+                        return Ok(ExitCode::SUCCESS);
+                    }
+                }
+            } else if arg.starts_with('-') && arg.len() > 1 {
                 for c in arg[1..].chars() {
                     match c {
                         $(ch if ch == stringify!($opt).chars().next().unwrap() => $set, )*
                         _ => {
-                            $on_invalid(c);
+                            $on_invalid(format!("-{c}"));
                             return Ok(ExitCode::SUCCESS);
                         }
                     }
@@ -1,9 +1,25 @@
 #[derive(Debug)]
 pub enum Command {
     Fg,
+    Bg,
+    Disown,
     Cd,
     Ls,
+    Tree,
+    Cp,
+    Mv,
+    Rm,
+    Cat,
+    Du,
+    Dirs,
+    Pushd,
+    Popd,
     Jobs,
+    Kill,
+    History,
+    Alias,
+    Unalias,
+    Set,
     Help,
     Exit,
     Source,
@@ -14,6 +30,8 @@ pub enum Command {
 #[derive(Debug)]
 pub enum InternalCommand {
     Fg,
+    Bg,
+    Disown,
     Pid,
     Jobs,
     Kill,
@@ -26,11 +44,27 @@ impl Command {
     pub fn from_str(cmd: &str, args: &[String]) -> Command {
         match cmd {
             "fg" => Command::Fg,
+            "bg" => Command::Bg,
+            "disown" => Command::Disown,
             "cd" => Command::Cd,
             "ls" => Command::Ls,
+            "tree" => Command::Tree,
+            "cp" => Command::Cp,
+            "mv" => Command::Mv,
+            "rm" => Command::Rm,
+            "cat" => Command::Cat,
+            "du" => Command::Du,
+            "dirs" => Command::Dirs,
+            "pushd" => Command::Pushd,
+            "popd" => Command::Popd,
             "exit" => Command::Exit,
             "jobs" => Command::Jobs,
-            "source" => Command::Source,
+            "kill" => Command::Kill,
+            "history" => Command::History,
+            "alias" => Command::Alias,
+            "unalias" => Command::Unalias,
+            "set" => Command::Set,
+            "source" | "." => Command::Source,
             "help" | "?" => Command::Help,
             "tish" if !args.is_empty() => {
                 if args.len() > 2 {
@@ -49,12 +83,14 @@ impl InternalCommand {
     pub fn from_str(cmd: &str, args: &[String]) -> InternalCommand {
         match cmd {
             "fg" => InternalCommand::Fg,
+            "bg" => InternalCommand::Bg,
+            "disown" => InternalCommand::Disown,
             "pid" => InternalCommand::Pid,
             "kill" => InternalCommand::Kill,
             "jobs" => InternalCommand::Jobs,
             "help" | "?" => InternalCommand::Help,
             "tish" if !args.is_empty() => {
-                if args.len() > 2 {
+                if args.len() > 2 && args[0] != "kill" {
                     InternalCommand::Help
                 } else {
                     InternalCommand::from_str(&args[0], &[])
@@ -40,7 +40,13 @@ impl EnvManager {
                 self.pos = 0;
                 result.push_str(&self.expand_variable());
             } else {
-                result.push_str(&token);
+                // re-escape so the line tokenizes into the same words again
+                for c in token.chars() {
+                    if c == '\\' || c == '\'' || c == '"' || c.is_whitespace() {
+                        result.push('\\');
+                    }
+                    result.push(c);
+                }
             }
         }
 
@@ -144,31 +150,63 @@ impl EnvManager {
         result
     }
 
+    // condenses components from the left only until the path fits in `limit` characters
+    pub fn condensed_path_within(&self, limit: usize, keep: usize) -> String {
+        let contracted = self.contract_home();
+        let mut parts: Vec<String> = contracted.split('/').map(String::from).collect();
+        let condensable = parts.len().saturating_sub(keep.max(1));
+        let width = |parts: &[String]| parts.iter().map(|part| part.chars().count()).sum::<usize>() + parts.len().saturating_sub(1);
+
+        for i in 0..condensable {
+            if width(&parts) <= limit {
+                break;
+            }
+
+            let part = &parts[i];
+            let prefix_len = if part.starts_with('.') { 2 } else { 1 };
+            if part != "~" && part.chars().count() > prefix_len {
+                parts[i] = part.chars().take(prefix_len).collect();
+            }
+        }
+
+        parts.join("/")
+    }
+
     pub fn expand_home(&mut self) -> String {
         let path = self.take_while(|c| !c.is_whitespace());
 
         if path.is_empty() {
-            if let Ok(home) = std::env::var("HOME") {
-                return home;
-            }
-            return "~".to_string();
+            return Self::expand_tilde("~");
         }
 
-        let path = if path.starts_with('~') { &path[1..] } else { path.as_str() };
+        match path.starts_with('~') {
+            true => Self::expand_tilde(&path),
+            false => Self::expand_tilde(&format!("~{path}")),
+        }
+    }
+
+    // `~`, `~/path` and `~user/path`, anything else is returned unchanged
+    pub fn expand_tilde(path: &str) -> String {
+        let Some(path) = path.strip_prefix('~') else {
+            return path.to_string();
+        };
 
-        if path.starts_with('/') {
+        if path.is_empty() || path.starts_with('/') {
             if let Ok(home) = std::env::var("HOME") {
                 return format!("{home}{path}");
             }
         } else {
-            let (user, rest) = path.split_once('/').unwrap_or((path, ""));
+            let (user, rest) = path.split_once('/').map_or((path, None), |(user, rest)| (user, Some(rest)));
             #[cfg(unix)]
             {
                 if let Ok(username) = std::ffi::CString::new(user) {
                     let passwd = unsafe { libc::getpwnam(username.as_ptr()) };
                     if !passwd.is_null() {
                         let home = unsafe { std::ffi::CStr::from_ptr((*passwd).pw_dir) }.to_string_lossy();
-                        return format!("{home}/{rest}");
+                        return match rest {
+                            Some(rest) => format!("{home}/{rest}"),
+                            None => home.into_owned(),
+                        };
                     }
                 }
             }
@@ -36,6 +36,9 @@ pub struct Group {
     pub(crate) name_arc: Arc<OsStr>,
 }
 
+// differs from getuid() under setuid binaries, and decides whether the shell has root privileges
+pub fn effective_uid() -> uid_t { unsafe { libc::geteuid() } }
+
 pub fn get_username() -> Result<String, Box<dyn Error>> {
     let uid = unsafe { libc::getuid() };
     let pw = unsafe { getpwuid(uid) };
@@ -1,4 +1,12 @@
-use crate::shell::highlight;
+use crate::{
+    os::{env::EnvManager, size::dimensions},
+    shell::{
+        completion,
+        highlight::{self, TokenType},
+        tokenizer::{Heredoc, Tokenizer},
+    },
+    tty::visible_width,
+};
 use anyhow::{anyhow, Result};
 use parking_lot::RwLock;
 use pat::Tap;
@@ -9,35 +17,73 @@ use std::{
     env,
     fs::{self, DirEntry},
     path::PathBuf,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
 
 use rustyline::{
-    completion::Completer,
+    completion::{Completer, Pair},
+    config::Configurer,
     error::ReadlineError,
     highlight::{CmdKind, Highlighter, MatchingBracketHighlighter},
     hint::Hinter,
     history::{FileHistory, History, SearchDirection},
     validate::{MatchingBracketValidator, Validator},
-    ColorMode, CompletionType, Config, Context, Editor, Helper,
+    Cmd, ColorMode, CompletionType, ConditionalEventHandler, Config, Context, Editor, Event, EventContext, EventHandler, Helper, KeyCode, KeyEvent, Modifiers, Movement,
+    RepeatCount,
 };
 
 type Readline<T> = Editor<T, FileHistory>;
 type Receiver = Result<String, ReadlineError>;
 
+// set by `history -c`, the editor thread drops its in-memory history before the next prompt
+static CLEAR_HISTORY: AtomicBool = AtomicBool::new(false);
+
+// the typed line of a `!` expansion, which the editor thread swaps for the expansion before the next prompt
+static EXPANDED_HISTORY: Mutex<Option<String>> = Mutex::new(None);
+
+// mirrors config.completion_ignore_case, which the editor thread can't read from lua itself
+static COMPLETION_IGNORE_CASE: AtomicBool = AtomicBool::new(false);
+
+// mirrors config.lua_prefix, so the highlighter colors the same lines as lua that run as lua
+static LUA_PREFIX: Mutex<String> = Mutex::new(String::new());
+
+// styles from config.colors, handed to the editor thread's highlighter before the next prompt
+static HIGHLIGHT_STYLES: Mutex<Vec<(TokenType, String)>> = Mutex::new(Vec::new());
+
 pub struct AsyncLineReader {
     buffer: String,
     continuation: bool,
-    request_tx: mpsc::Sender<String>,
+    history_size: Arc<AtomicUsize>,
+    request_tx: mpsc::Sender<(String, String)>,
+    rprompt: String,
     response_rx: mpsc::Receiver<Receiver>,
 }
 
+pub enum KeyAction {
+    Command(String),
+    Insert(String),
+}
+
+// right-arrow takes the whole hint only at the end of the line, anywhere else it just moves the cursor
+struct AcceptHint;
+
+impl ConditionalEventHandler for AcceptHint {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        (ctx.has_hint() && ctx.pos() == ctx.line().len()).then_some(Cmd::CompleteHint)
+    }
+}
+
 struct TishHelper {
     highlighter: highlight::Highlighter,
     bracket_highlighter: MatchingBracketHighlighter,
     validator: MatchingBracketValidator,
     command_cache: Arc<RwLock<HashMap<String, bool>>>,
     current_line: Arc<RwLock<String>>,
+    line_width: AtomicUsize,
+    rprompt: String,
 }
 
 impl TishHelper {
@@ -48,17 +94,39 @@ impl TishHelper {
             validator: MatchingBracketValidator::new(),
             command_cache: Arc::new(RwLock::new(HashMap::new())),
             current_line: Arc::new(RwLock::new(String::new())),
+            line_width: AtomicUsize::new(0),
+            rprompt: String::new(),
+        }
+    }
+
+    // the right prompt is drawn after saving the cursor, so rustyline only measures the left prompt,
+    // and it hides once the line would run into it. the width lags one redraw since the line renders after the prompt
+    fn right_prompt(&self, prompt: &str) -> Option<String> {
+        if self.rprompt.is_empty() {
+            return None;
         }
+
+        let (cols, _) = dimensions()?;
+        let width = visible_width(&self.rprompt);
+        let used = visible_width(prompt.rsplit('\n').next().unwrap_or(prompt)) + self.line_width.load(Ordering::Relaxed);
+
+        if used + width >= cols {
+            return None;
+        }
+
+        Some(format!("\x1b7\x1b[{}G{}\x1b8", cols - width + 1, self.rprompt))
     }
 
     fn update_command_status(&self, line: &str) {
         let mut cache = self.command_cache.write();
-        let words: Vec<&str> = line.split_whitespace().collect();
         cache.clear();
 
-        if let Some(first_word) = words.first() {
-            if !cache.contains_key(*first_word) {
-                cache.insert(first_word.to_string(), self.highlighter.command_exists(first_word));
+        // every word that starts a command, so `foo && ls` checks both foo and ls
+        for segment in line.split(['|', '&', ';']) {
+            if let Some(word) = segment.split_whitespace().next() {
+                if !cache.contains_key(word) {
+                    cache.insert(word.to_string(), self.highlighter.command_exists(word));
+                }
             }
         }
     }
@@ -86,12 +154,61 @@ impl TishHelper {
         result
     }
 
+    // the words of the command under the cursor, ending with the (possibly empty) word being completed
+    fn command_words(line: &str) -> Vec<String> {
+        let segment = line.rsplit(['|', '&', ';']).next().unwrap_or(line);
+        let mut words: Vec<String> = segment.split_whitespace().map(str::to_string).collect();
+
+        if segment.is_empty() || segment.ends_with(char::is_whitespace) {
+            words.push(String::new());
+        }
+
+        words
+    }
+
+    // a directory leaves the cursor after its `/` to keep descending, anything else gets a space for the next word
+    // unless one already follows the cursor
+    fn into_candidates(completions: Vec<String>, spaced: bool) -> Vec<Pair> {
+        completions
+            .into_iter()
+            .map(|display| {
+                let replacement = match spaced && !display.ends_with('/') {
+                    true => format!("{display} "),
+                    false => display.clone(),
+                };
+                Pair { display, replacement }
+            })
+            .collect()
+    }
+
+    // only paths get quoted, history and command candidates are inserted as they are
+    fn quote_candidate(candidate: String, raw_word: &str) -> String {
+        let is_path = fs::symlink_metadata(EnvManager::expand_tilde(&candidate)).is_ok();
+        if !is_path || !candidate.contains(|c: char| c.is_whitespace() || matches!(c, '\'' | '"' | '\\')) {
+            return candidate;
+        }
+
+        let close = |quote: char| if candidate.ends_with('/') { String::new() } else { quote.to_string() };
+
+        match raw_word.chars().next() {
+            Some('"') => format!("\"{}{}", candidate.replace('\\', "\\\\").replace('"', "\\\""), close('"')),
+            Some('\'') if !candidate.contains('\'') => format!("'{candidate}{}", close('\'')),
+            _ => candidate.chars().fold(String::new(), |mut escaped, c| {
+                if c.is_whitespace() || matches!(c, '\'' | '"' | '\\') {
+                    escaped.push('\\');
+                }
+                escaped.push(c);
+                escaped
+            }),
+        }
+    }
+
     fn get_completions(&self, input: &str, ctx: &Context<'_>) -> Vec<String> {
         let mut completions = Vec::new();
 
-        let commands = ["cd", "ls", "exit", "help", "?", "source", "echo", "tish"];
+        let commands = ["cd", "ls", "tree", "dirs", "pushd", "popd", "exit", "help", "?", "source", "echo", "tish", "fg", "bg", "jobs", "kill", "disown", "history", "alias", "unalias", "set", "."];
         let (cmd, word) = input.split_once(char::is_whitespace).map_or(("", input), |(c, w)| (c, w));
-        let dirs_only = cmd == "cd" || cmd == "ls";
+        let dirs_only = matches!(cmd, "cd" | "ls" | "pushd");
 
         if word.starts_with("~/") {
             Self::get_home_completions(&mut completions, word, dirs_only)
@@ -106,26 +223,32 @@ impl TishHelper {
                     .flatten()
                     .filter_map(Result::ok)
                     .map(|e| e.file_name().to_string_lossy().to_string())
-                    .filter(|name| name.starts_with(word))
+                    .filter(|name| completion::fuzzy_score(name, word).is_some())
                     .for_each(|name| completions.push(name));
             }
         }
 
         if !dirs_only {
-            completions.extend((*crate::LUA_FN).iter().filter(|r| r.key().starts_with(word)).map(|r| r.key().clone()));
+            completions.extend((*crate::LUA_FN).iter().filter(|r| completion::fuzzy_score(r.key(), word).is_some()).map(|r| r.key().clone()));
             completions.extend(self.get_history_matches(word, ctx.history()));
         }
 
         if completions.is_empty() {
-            completions.extend(commands.iter().filter(|cmd| cmd.starts_with(word)).map(ToString::to_string));
+            completions.extend(commands.iter().filter(|cmd| completion::fuzzy_score(cmd, word).is_some()).map(ToString::to_string));
         }
 
-        completions.sort();
-        completions.dedup();
-
+        completion::rank(&mut completions, word, COMPLETION_IGNORE_CASE.load(Ordering::Relaxed));
         return completions;
     }
 
+    // config.completion_ignore_case only applies to file names, commands stay case sensitive
+    fn matches_file(name: &str, prefix: &str) -> bool {
+        match COMPLETION_IGNORE_CASE.load(Ordering::Relaxed) {
+            true => completion::fuzzy_score(&name.to_lowercase(), &prefix.to_lowercase()).is_some(),
+            false => completion::fuzzy_score(name, prefix).is_some(),
+        }
+    }
+
     fn get_home_completions(completions: &mut Vec<String>, word: &str, dirs_only: bool) {
         if let Some(home) = dirs::home_dir() {
             let replace_path = |path: &str| {
@@ -149,7 +272,7 @@ impl TishHelper {
                         if let Some(name) = entry.file_name().to_str() {
                             let is_hidden = name.starts_with(".");
                             let allow_hidden = search_name.starts_with(".");
-                            let matches_search = name.starts_with(&search_name);
+                            let matches_search = Self::matches_file(name, &search_name);
 
                             matches_search && (!is_hidden || allow_hidden)
                         } else {
@@ -185,7 +308,7 @@ impl TishHelper {
                     entry.file_name().to_str().map_or(false, |name| {
                         let is_hidden = name.starts_with(".");
                         let show_hidden = file_prefix.starts_with(".");
-                        name.starts_with(file_prefix) && (!is_hidden || show_hidden)
+                        Self::matches_file(name, file_prefix) && (!is_hidden || show_hidden)
                     })
                 })
                 .collect::<Vec<DirEntry>>()
@@ -210,13 +333,27 @@ impl TishHelper {
 impl Helper for TishHelper {}
 
 impl Completer for TishHelper {
-    type Candidate = String;
+    type Candidate = Pair;
 
     fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
         self.update_command_status(line);
-        let (start, _) = line[..pos].rsplit_once(char::is_whitespace).map_or((0, line), |(_, w)| (pos - w.len(), w));
-        let completions = self.get_completions(line, ctx);
-        Ok((start, completions))
+        let start = Tokenizer::last_word_start(&line[..pos]);
+        let raw_word = &line[start..pos];
+        let spaced = !line[pos..].starts_with(char::is_whitespace);
+
+        if let Some(candidates) = completion::complete(&Self::command_words(&line[..pos])) {
+            return Ok((start, Self::into_candidates(candidates, spaced)));
+        }
+
+        // candidates are found for the word without its quotes or escapes, then quoted back the way it was typed
+        let word = Tokenizer::new(raw_word).next().unwrap_or_default();
+        let input = match line[..start].split_whitespace().next() {
+            Some(cmd) => format!("{cmd} {word}"),
+            None => word,
+        };
+
+        let completions = self.get_completions(&input, ctx).into_iter().map(|candidate| Self::quote_candidate(candidate, raw_word)).collect();
+        Ok((start, Self::into_candidates(completions, spaced)))
     }
 }
 
@@ -230,6 +367,14 @@ impl Hinter for TishHelper {
 
         *self.current_line.write() = line.to_string();
 
+        // like fish, the most recent history line that extends what was typed wins over completions
+        let history = ctx.history();
+        if let Ok(Some(found)) = history.starts_with(line, history.len().saturating_sub(1), SearchDirection::Reverse) {
+            if let Some(hint) = found.entry.strip_prefix(line).filter(|hint| !hint.is_empty()) {
+                return Some(hint.to_string());
+            }
+        }
+
         let completions = self.get_completions(line, ctx);
         if let Some(hint) = completions.iter().find(|s| s.starts_with(line)) {
             return Some(hint.strip_prefix(line).unwrap_or(hint).to_string());
@@ -240,13 +385,8 @@ impl Hinter for TishHelper {
             return None;
         }
 
-        completions.first().map(|s| {
-            if let Some(common) = line.rsplit_once(char::is_whitespace) {
-                s.strip_prefix(common.1).unwrap_or(s).to_string()
-            } else {
-                s.strip_prefix(line).unwrap_or(s).to_string()
-            }
-        })
+        // fuzzy matches can't extend the typed word, so only a prefix match becomes the hint
+        completions.iter().find_map(|s| s.strip_prefix(word)).map(ToString::to_string)
     }
 }
 
@@ -259,8 +399,10 @@ impl Highlighter for TishHelper {
 
     fn highlight<'l>(&self, line: &'l str, _: usize) -> std::borrow::Cow<'l, str> {
         self.update_command_status(line);
+        self.line_width.store(visible_width(line), Ordering::Relaxed);
         let cache = self.command_cache.read();
-        self.highlighter.highlight_with_cache(line, &cache).into()
+        let lua_prefix = LUA_PREFIX.lock().expect("Able to lock lua prefix");
+        self.highlighter.highlight_with_cache(line, &cache, &lua_prefix).into()
     }
 
     fn highlight_hint<'h>(&self, hint: &'h str) -> std::borrow::Cow<'h, str> {
@@ -283,7 +425,10 @@ impl Highlighter for TishHelper {
 
     fn highlight_prompt<'b, 's: 'b, 'p: 'b>(&'s self, prompt: &'p str, default: bool) -> std::borrow::Cow<'b, str> {
         if default {
-            std::borrow::Cow::Borrowed(prompt)
+            match self.right_prompt(prompt) {
+                Some(rprompt) => std::borrow::Cow::Owned(format!("{prompt}{rprompt}")),
+                None => std::borrow::Cow::Borrowed(prompt),
+            }
         } else {
             std::borrow::Cow::Owned(format!("\x1b[1;32m{}\x1b[0m", prompt))
         }
@@ -291,13 +436,13 @@ impl Highlighter for TishHelper {
 }
 
 impl AsyncLineReader {
-    pub fn new() -> Result<Self> {
-        let (request_tx, mut request_rx) = mpsc::channel::<String>(32);
+    pub fn new(history_size: usize) -> Result<Self> {
+        let (request_tx, mut request_rx) = mpsc::channel::<(String, String)>(32);
         let (response_tx, response_rx) = mpsc::channel::<Receiver>(32);
 
         let config = Config::builder()
             .auto_add_history(true)
-            .max_history_size(500)?
+            .max_history_size(history_size)?
             .history_ignore_dups(true)?
             .color_mode(ColorMode::Enabled)
             .completion_type(CompletionType::Fuzzy)
@@ -307,22 +452,76 @@ impl AsyncLineReader {
         let mut editor: Readline<TishHelper> = Readline::with_config(config)?;
 
         editor.set_helper(Some(TishHelper::new()));
-        editor.bind_sequence(rustyline::KeyEvent::new('\r', rustyline::Modifiers::NONE), rustyline::Cmd::AcceptLine);
+        editor.bind_sequence(KeyEvent::new('\r', Modifiers::NONE), Cmd::AcceptLine);
+        editor.bind_sequence(KeyEvent(KeyCode::Right, Modifiers::NONE), EventHandler::Conditional(Box::new(AcceptHint)));
 
-        let history_file = {
-            let mut file = dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))?;
-            file.push(".tish_history");
-            file
-        };
+        let history_file = Self::history_file()?;
 
-        if history_file.exists() {
-            if let Err(e) = editor.load_history(&history_file) {
-                eprintln!("Failed to load history: {}", e);
-            }
-        }
+        let history_size = Arc::new(AtomicUsize::new(history_size));
+        let thread_history_size = history_size.clone();
 
         std::thread::spawn(move || {
-            while let Some(prompt) = request_rx.blocking_recv() {
+            let mut applied_size = thread_history_size.load(Ordering::SeqCst);
+            let mut history_loaded = false;
+
+            while let Some((prompt, rprompt)) = request_rx.blocking_recv() {
+                let size = thread_history_size.load(Ordering::SeqCst);
+                if size != applied_size {
+                    if let Err(e) = editor.set_max_history_size(size) {
+                        eprintln!("Failed to set history size: {}", e);
+                    }
+                    applied_size = size;
+                }
+
+                // history is loaded lazily so a size from .tishrc applies before truncation
+                if !history_loaded && history_file.exists() {
+                    if let Err(e) = editor.load_history(&history_file) {
+                        eprintln!("Failed to load history: {}", e);
+                    }
+                }
+                history_loaded = true;
+
+                for (key, cmd) in crate::KEY_BINDINGS.lock().expect("Able to lock key bindings").drain(..) {
+                    editor.bind_sequence(key, cmd);
+                }
+
+                if let Some(helper) = editor.helper_mut() {
+                    for (token_type, style) in HIGHLIGHT_STYLES.lock().expect("Able to lock highlight styles").drain(..) {
+                        helper.highlighter.set_style(token_type, style);
+                    }
+                }
+
+                if CLEAR_HISTORY.swap(false, Ordering::SeqCst) {
+                    if let Err(e) = editor.clear_history() {
+                        eprintln!("Failed to clear history: {}", e);
+                    }
+                }
+
+                if let Some(typed) = EXPANDED_HISTORY.lock().expect("Able to lock expanded history").take() {
+                    let mut entries: Vec<String> = editor.history().iter().cloned().collect();
+                    if entries.pop().is_some_and(|last| last == typed) {
+                        if let Err(e) = editor.clear_history() {
+                            eprintln!("Failed to clear history: {}", e);
+                        }
+                        for entry in entries {
+                            if let Err(e) = editor.add_history_entry(entry) {
+                                eprintln!("Failed to add history entry: {}", e);
+                            }
+                        }
+                    }
+                }
+
+                for line in crate::HISTORY_QUEUE.lock().expect("Able to lock history queue").drain(..) {
+                    if let Err(e) = editor.add_history_entry(line) {
+                        eprintln!("Failed to add history entry: {}", e);
+                    }
+                }
+
+                if let Some(helper) = editor.helper_mut() {
+                    helper.rprompt = rprompt;
+                    helper.line_width.store(0, Ordering::Relaxed);
+                }
+
                 let result = editor.readline(&prompt);
                 if let Err(e) = editor.save_history(&history_file) {
                     eprintln!("Failed to save history: {}", e);
@@ -336,12 +535,256 @@ impl AsyncLineReader {
 
         Ok(Self {
             request_tx,
+            rprompt: String::new(),
             response_rx,
+            history_size,
             continuation: false,
             buffer: String::new(),
         })
     }
 
+    pub fn set_history_size(&self, size: usize) { self.history_size.store(size, Ordering::SeqCst); }
+
+    pub fn set_rprompt(&mut self, rprompt: String) { self.rprompt = rprompt; }
+
+    pub fn set_completion_ignore_case(&self, ignore_case: bool) { COMPLETION_IGNORE_CASE.store(ignore_case, Ordering::Relaxed); }
+
+    pub fn set_lua_prefix(&self, prefix: String) { *LUA_PREFIX.lock().expect("Able to lock lua prefix") = prefix; }
+
+    pub fn set_highlight_style(&self, name: &str, style: String) -> Result<()> {
+        let token_type = TokenType::from_name(name).ok_or_else(|| anyhow!("unknown highlight token: {}", name))?;
+        HIGHLIGHT_STYLES.lock().expect("Able to lock highlight styles").push((token_type, style));
+        Ok(())
+    }
+
+    // bindings are queued and applied by the editor thread before the next prompt
+    pub fn bind_key(sequence: &str, action: KeyAction) -> Result<()> {
+        let key = Self::parse_key(sequence)?;
+
+        let cmd = match action {
+            KeyAction::Insert(text) => Cmd::Insert(1, text),
+            KeyAction::Command(name) => match name.as_str() {
+                "accept_line" => Cmd::AcceptLine,
+                "abort" => Cmd::Abort,
+                "backward_char" => Cmd::Move(Movement::BackwardChar(1)),
+                "backward_kill_word" => Cmd::Kill(Movement::BackwardWord(1, rustyline::Word::Emacs)),
+                "backward_word" => Cmd::Move(Movement::BackwardWord(1, rustyline::Word::Emacs)),
+                "beginning_of_line" => Cmd::Move(Movement::BeginningOfLine),
+                "capitalize_word" => Cmd::CapitalizeWord,
+                "clear_line" => Cmd::Kill(Movement::WholeLine),
+                "clear_screen" => Cmd::ClearScreen,
+                "complete" => Cmd::Complete,
+                "complete_hint" => Cmd::CompleteHint,
+                "downcase_word" => Cmd::DowncaseWord,
+                "end_of_line" => Cmd::Move(Movement::EndOfLine),
+                "forward_char" => Cmd::Move(Movement::ForwardChar(1)),
+                "forward_word" => Cmd::Move(Movement::ForwardWord(1, rustyline::At::AfterEnd, rustyline::Word::Emacs)),
+                "history_search_backward" => Cmd::HistorySearchBackward,
+                "history_search_forward" => Cmd::HistorySearchForward,
+                "interrupt" => Cmd::Interrupt,
+                "kill_line" => Cmd::Kill(Movement::EndOfLine),
+                "next_history" => Cmd::NextHistory,
+                "noop" => Cmd::Noop,
+                "previous_history" => Cmd::PreviousHistory,
+                "reverse_search_history" => Cmd::ReverseSearchHistory,
+                "transpose_chars" => Cmd::TransposeChars,
+                "undo" => Cmd::Undo(1),
+                "upcase_word" => Cmd::UpcaseWord,
+                "yank" => Cmd::Yank(1, rustyline::Anchor::Before),
+                _ => return Err(anyhow!("unknown key action: {}", name)),
+            },
+        };
+
+        crate::KEY_BINDINGS.lock().expect("Able to lock key bindings").push((key, cmd));
+        Ok(())
+    }
+
+    fn parse_key(sequence: &str) -> Result<KeyEvent> {
+        let mut mods = Modifiers::NONE;
+        let mut rest = sequence;
+
+        loop {
+            let (modifier, key) = match rest.split_once('-') {
+                Some((modifier, key)) if !key.is_empty() => (modifier, key),
+                _ => break,
+            };
+
+            mods |= match modifier.to_lowercase().as_str() {
+                "c" | "ctrl" => Modifiers::CTRL,
+                "m" | "alt" | "meta" => Modifiers::ALT,
+                "s" | "shift" => Modifiers::SHIFT,
+                _ => return Err(anyhow!("unknown key modifier in {}", sequence)),
+            };
+            rest = key;
+        }
+
+        let code = match rest.to_lowercase().as_str() {
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "down" => KeyCode::Down,
+            "end" => KeyCode::End,
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "home" => KeyCode::Home,
+            "insert" => KeyCode::Insert,
+            "left" => KeyCode::Left,
+            "pagedown" => KeyCode::PageDown,
+            "pageup" => KeyCode::PageUp,
+            "right" => KeyCode::Right,
+            "space" => KeyCode::Char(' '),
+            "tab" => KeyCode::Tab,
+            "up" => KeyCode::Up,
+            key if key.len() > 1 && key.starts_with('f') => KeyCode::F(key[1..].parse().map_err(|_| anyhow!("unknown key: {}", sequence))?),
+            _ => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => return Err(anyhow!("unknown key: {}", sequence)),
+                }
+            }
+        };
+
+        Ok(KeyEvent::normalize(KeyEvent(code, mods)))
+    }
+
+    pub fn history_file() -> Result<PathBuf> {
+        let mut file = dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))?;
+        file.push(".tish_history");
+        Ok(file)
+    }
+
+    // saved entries followed by those still waiting for the editor to pick them up
+    pub fn history() -> Vec<String> {
+        let mut entries = Vec::new();
+
+        if let Some(contents) = Self::history_file().ok().and_then(|file| fs::read_to_string(file).ok()) {
+            let mut lines = contents.lines().peekable();
+            let escaped = lines.next_if_eq(&"#V2").is_some();
+
+            for line in lines.filter(|line| !line.is_empty()) {
+                entries.push(if escaped { Self::unescape_history(line) } else { line.to_string() });
+            }
+        }
+
+        entries.extend(crate::HISTORY_QUEUE.lock().expect("Able to lock history queue").iter().cloned());
+        entries
+    }
+
+    pub fn clear_history() -> Result<()> {
+        CLEAR_HISTORY.store(true, Ordering::SeqCst);
+        crate::HISTORY_QUEUE.lock().expect("Able to lock history queue").clear();
+
+        let file = Self::history_file()?;
+        if file.exists() {
+            fs::write(file, "")?;
+        }
+
+        Ok(())
+    }
+
+    // history keeps the expansion in place of the typed `!` form, like bash
+    pub fn replace_history(typed: &str, expanded: String) {
+        *EXPANDED_HISTORY.lock().expect("Able to lock expanded history") = Some(typed.to_string());
+        crate::HISTORY_QUEUE.lock().expect("Able to lock history queue").push(expanded);
+    }
+
+    // `!!`, `!n`, `!-n` and `!prefix` outside quotes, None when the line has nothing to expand
+    pub fn expand_history(line: &str) -> Result<Option<String>> {
+        if !line.contains('!') {
+            return Ok(None);
+        }
+
+        // the editor saves a line before it runs, so skip the one being expanded
+        let mut entries = Self::history();
+        if entries.last().is_some_and(|last| last == line) {
+            entries.pop();
+        }
+
+        let mut result = String::with_capacity(line.len());
+        let mut chars = line.char_indices().peekable();
+        let mut quote = None;
+        let mut expanded = false;
+
+        while let Some((idx, c)) = chars.next() {
+            match (c, quote) {
+                ('\\', Some('\'')) => result.push(c),
+                ('\\', _) => match chars.next() {
+                    Some((_, '!')) if quote.is_none() => result.push('!'),
+                    Some((_, next)) => result.extend([c, next]),
+                    None => result.push(c),
+                },
+                ('\'' | '"', None) => {
+                    quote = Some(c);
+                    result.push(c);
+                }
+                (c, Some(open)) if c == open => {
+                    quote = None;
+                    result.push(c);
+                }
+                ('!', None) => {
+                    let rest = &line[idx + 1..];
+                    let len = match rest.chars().next() {
+                        Some('!') => 1,
+                        Some(next) if !next.is_whitespace() && !matches!(next, '=' | '(' | ')' | ';' | '|' | '&') => {
+                            rest.find(|c: char| c.is_whitespace() || matches!(c, ';' | '|' | '&' | '(' | ')')).unwrap_or(rest.len())
+                        }
+                        _ => {
+                            result.push(c);
+                            continue;
+                        }
+                    };
+
+                    let spec = &rest[..len];
+                    let entry = Self::history_event(&entries, spec).ok_or_else(|| anyhow!("!{spec}: event not found"))?;
+
+                    result.push_str(entry);
+                    expanded = true;
+
+                    while chars.next_if(|&(next, _)| next <= idx + len).is_some() {}
+                }
+                _ => result.push(c),
+            }
+        }
+
+        Ok(expanded.then_some(result))
+    }
+
+    fn history_event<'a>(entries: &'a [String], spec: &str) -> Option<&'a String> {
+        if spec == "!" {
+            return entries.last();
+        }
+
+        if let Some(back) = spec.strip_prefix('-').and_then(|back| back.parse::<usize>().ok()) {
+            return entries.len().checked_sub(back).and_then(|idx| entries.get(idx));
+        }
+
+        match spec.parse::<usize>() {
+            Ok(number) => number.checked_sub(1).and_then(|idx| entries.get(idx)),
+            Err(_) => entries.iter().rev().find(|entry| entry.starts_with(spec)),
+        }
+    }
+
+    fn unescape_history(line: &str) -> String {
+        let mut result = String::with_capacity(line.len());
+        let mut chars = line.chars();
+
+        while let Some(c) = chars.next() {
+            match (c, chars.clone().next()) {
+                ('\\', Some('n')) => {
+                    result.push('\n');
+                    chars.next();
+                }
+                ('\\', Some('\\')) => {
+                    result.push('\\');
+                    chars.next();
+                }
+                _ => result.push(c),
+            }
+        }
+
+        result
+    }
+
     pub fn clear_buffer(&mut self) {
         self.buffer.clear();
         self.continuation = false;
@@ -351,26 +794,37 @@ impl AsyncLineReader {
         loop {
             let current_prompt = if self.continuation { "> " } else { prompt };
 
-            self.request_tx.send(current_prompt.to_owned()).await.map_err(|_| ReadlineError::Interrupted)?;
+            let rprompt = if self.continuation { String::new() } else { self.rprompt.clone() };
+            self.request_tx.send((current_prompt.to_owned(), rprompt)).await.map_err(|_| ReadlineError::Interrupted)?;
 
             match self.response_rx.recv().await.unwrap_or(Err(ReadlineError::Interrupted)) {
                 Ok(line) => {
-                    if line.ends_with('\\') {
-                        self.buffer.push_str(&line[..line.len() - 1]);
+                    if let Some(line) = line.strip_suffix('\\') {
+                        self.buffer.push_str(line);
+                        self.buffer.push('\n');
+                        self.continuation = true;
+                        continue;
+                    }
+
+                    self.buffer.push_str(&line);
+
+                    // keep reading while a quote or heredoc is open or the line ends on `|`, `||` or `&&`
+                    let heredoc = Heredoc::split(&self.buffer);
+                    let command = heredoc.as_ref().map_or(self.buffer.as_str(), |(command, _)| command.as_str());
+                    let trimmed = command.trim_end();
+
+                    if heredoc.as_ref().is_some_and(|(_, heredoc)| !heredoc.terminated)
+                        || Tokenizer::has_open_quote(command)
+                        || trimmed.ends_with('|')
+                        || trimmed.ends_with("&&")
+                    {
                         self.buffer.push('\n');
                         self.continuation = true;
                         continue;
-                    } else {
-                        if self.continuation {
-                            self.buffer.push_str(&line);
-                            let result = self.buffer.clone();
-                            self.buffer.clear();
-                            self.continuation = false;
-                            return Ok(result);
-                        } else {
-                            return Ok(line);
-                        }
                     }
+
+                    self.continuation = false;
+                    return Ok(std::mem::take(&mut self.buffer));
                 }
                 Err(e) => {
                     self.clear_buffer();
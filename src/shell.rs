@@ -1,4 +1,5 @@
 pub mod alias;
+pub mod completion;
 pub mod git;
 pub mod highlight;
 pub mod signals;
@@ -7,17 +8,21 @@ pub mod tokenizer;
 use crate::{
     args::TishArgs,
     command::{LuaState, TishCommand},
-    os::{env::EnvManager, user},
+    os::{env::EnvManager, size::dimensions, user},
     prelude::*,
     readline::AsyncLineReader,
-    template::Template,
-    tty::get_tty_name_or_default,
+    template::{CompiledTemplate, Template},
+    tty::{get_tty_name_or_default, stdin_is_tty, stdout_is_tty, visible_width},
 };
 
 use std::{
+    collections::HashMap,
     env,
-    path::PathBuf,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
     process::{self, ExitCode},
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
@@ -30,8 +35,60 @@ pub struct TishShell {
     pub lua: LuaState,
     pub home: Option<PathBuf>,
     pub signal_handler: SignalHandler,
+    pub term_size: Option<(usize, usize)>,
+    pub last_status: AtomicU8,
+    pub options: ShellOptions,
 
     readline: AsyncLineReader,
+    prompt: Option<CompiledTemplate>,
+    rprompt: Option<CompiledTemplate>,
+    transient_prompt: Option<CompiledTemplate>,
+    startup: Option<StartupProfile>,
+}
+
+#[derive(Default)]
+pub struct ShellOptions {
+    pub errexit: AtomicBool,
+    pub xtrace: AtomicBool,
+}
+
+impl ShellOptions {
+    pub fn flag(&self, name: &str) -> Option<&AtomicBool> {
+        match name {
+            "e" | "errexit" => Some(&self.errexit),
+            "x" | "xtrace" => Some(&self.xtrace),
+            _ => None,
+        }
+    }
+
+    pub fn print(&self) {
+        for (name, flag) in [("errexit", &self.errexit), ("xtrace", &self.xtrace)] {
+            println!("{name:<10}{}", if flag.load(Ordering::Relaxed) { "on" } else { "off" });
+        }
+    }
+}
+
+struct StartupProfile {
+    started: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl StartupProfile {
+    fn record(&mut self, phase: &'static str, since: Instant) { self.phases.push((phase, since.elapsed())); }
+
+    fn print(&self) {
+        eprintln!("tish startup profile:");
+        for (phase, elapsed) in &self.phases {
+            eprintln!("  {phase:<14}{:>10.2}ms", elapsed.as_secs_f64() * 1000.0);
+        }
+        eprintln!("  {:<14}{:>10.2}ms", "total", self.started.elapsed().as_secs_f64() * 1000.0);
+    }
+}
+
+struct Prompts {
+    left: String,
+    right: Option<String>,
+    transient: Option<String>,
 }
 
 impl TishShell {
@@ -50,31 +107,57 @@ impl TishShell {
             libc::signal(libc::SIGTTIN, libc::SIG_IGN);
         }
 
+        let started = Instant::now();
+        let lua = LuaState::new()?;
+        let history_size = lua.get_config_value("history_size")?;
+
         let mut shell = Self {
+            lua,
             args: args.to_owned(),
-            lua: LuaState::new()?,
             home: dirs::home_dir(),
-            readline: AsyncLineReader::new()?,
+            readline: AsyncLineReader::new(history_size)?,
             signal_handler: SignalHandler::new(),
+            term_size: dimensions(),
+            last_status: AtomicU8::new(0),
+            options: ShellOptions::default(),
+            prompt: None,
+            rprompt: None,
+            transient_prompt: None,
+            startup: args.profile.then(|| StartupProfile { started, phases: Vec::new() }),
         };
 
-        if !args.no_env {
+        shell.record_phase("lua runtime", started);
+
+        if !args.no_env && !args.norc {
+            let phase = Instant::now();
             shell.load_config()?;
+            shell.readline.set_history_size(shell.lua.get_config_value("history_size")?);
+            shell.record_phase(".tishrc", phase);
         }
 
-        if args.login {
+        if args.login && !args.no_env {
+            let phase = Instant::now();
             shell.load_profile()?;
+            shell.record_phase(".tish_profile", phase);
         }
 
-        if !args.headless {
+        let script = args.arguments.as_deref().filter(|line| Self::is_shell_script(line)).map(PathBuf::from);
+
+        if !args.headless && script.is_none() && (args.interactive || stdin_is_tty()) {
             shell.login_message()?;
         }
 
         if let Some(line) = args.arguments {
-            let line = alias::resolve_command(line);
-            let fmt_lua = LuaState::transform_lua(&line);
-            if let Err(_) = shell.lua.eval(&fmt_lua) {
-                let status = shell.execute_command(&line).await;
+            let phase = Instant::now();
+            let status = match script {
+                Some(path) => Some(shell.run_script(&path).await?),
+                None if shell.eval_lua_line(&line).is_none() => Some(shell.execute_command(&line).await),
+                None => None,
+            };
+
+            if let Some(status) = status {
+                shell.record_phase("command", phase);
+                shell.print_startup_profile();
                 let raw_code = unsafe { std::mem::transmute::<ExitCode, u8>(status) };
                 process::exit(raw_code as i32);
             }
@@ -83,6 +166,49 @@ impl TishShell {
         Ok(shell)
     }
 
+    // .lua and .tish files already run through the lua engine as Command::Script
+    fn is_shell_script(line: &str) -> bool { Path::new(line).is_file() && !line.ends_with(".lua") && !line.ends_with(".tish") }
+
+    async fn run_script(&self, path: &Path) -> Result<ExitCode> {
+        let script = std::fs::read_to_string(path).map_err(|err| anyhow!("tish: {}: {err}", path.display()))?;
+        Ok(self.run_lines(&script).await)
+    }
+
+    // every line runs the way it would at the prompt, and `#` comments cover a leading shebang
+    pub async fn run_lines(&self, script: &str) -> ExitCode {
+        let mut status = ExitCode::SUCCESS;
+
+        for line in script.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            status = self.run_line(&line.to_string()).await;
+
+            if self.options.errexit.load(Ordering::Relaxed) && status != ExitCode::SUCCESS {
+                break;
+            }
+        }
+
+        status
+    }
+
+    async fn run_line(&self, line: &String) -> ExitCode {
+        match self.eval_lua_line(line) {
+            Some(status) => status,
+            None => self.execute_command(line).await,
+        }
+    }
+
+    fn record_phase(&mut self, phase: &'static str, since: Instant) {
+        if let Some(startup) = &mut self.startup {
+            startup.record(phase, since);
+        }
+    }
+
+    // printed once, right before the first prompt or before exiting when there is none
+    fn print_startup_profile(&mut self) {
+        if let Some(startup) = self.startup.take() {
+            startup.print();
+        }
+    }
+
     fn login_message(&self) -> Result<ExitCode> {
         dotfile! {
             not, self.home => ".hushlogin",
@@ -98,7 +224,30 @@ impl TishShell {
     fn load_config(&self) -> Result<ExitCode> {
         dotfile! {
             self.home => ".tishrc",
-            |config| self.lua.eval_file(config)
+            |config| self.lua.eval_file(config).inspect(|_| self.apply_config_colors())
+        }
+    }
+
+    // values are parsed like prompt styles, so `#RRGGBB` follows config.truecolor too
+    fn apply_config_colors(&self) {
+        let colors = match self.lua.get_config_value::<Option<HashMap<String, String>>>("colors") {
+            Ok(colors) => colors.unwrap_or_default(),
+            Err(err) => return eprintln!("tish: config.colors: {err}"),
+        };
+
+        let mut tmpl = Template::new("");
+        if let Ok(Some(truecolor)) = self.lua.get_config_value::<Option<bool>>("truecolor") {
+            tmpl.set_truecolor(truecolor);
+        }
+
+        for (name, style) in colors {
+            let Some(escape) = tmpl.style_escape(&style) else {
+                eprintln!("tish: config.colors.{name}: unknown style {style:?}");
+                continue;
+            };
+            if let Err(err) = self.readline.set_highlight_style(&name, escape) {
+                eprintln!("tish: config.colors.{name}: {err}");
+            }
         }
     }
 
@@ -109,69 +258,210 @@ impl TishShell {
         }
     }
 
-    fn format_prompt(&self) -> Result<String> {
+    fn compile_cached(cached: Option<CompiledTemplate>, source: &str) -> CompiledTemplate {
+        match cached {
+            Some(compiled) if compiled.source() == source => compiled,
+            _ => Template::new(source).compile(),
+        }
+    }
+
+    // the right and transient prompts share every variable with the left one, so all of them render from a single pass
+    fn format_prompt(&mut self) -> Result<Prompts> {
         let str: String = self.lua.get_config_value("prompt")?;
+        let rstr = self.lua.get_config_value::<Option<String>>("rprompt")?.filter(|rprompt| !rprompt.is_empty());
+        let tstr = self.lua.get_config_value::<Option<String>>("transient_prompt")?.filter(|transient| !transient.is_empty());
+
+        let compiled = Self::compile_cached(self.prompt.take(), &str);
+        let rcompiled = rstr.map(|rstr| Self::compile_cached(self.rprompt.take(), &rstr));
+        let tcompiled = tstr.map(|tstr| Self::compile_cached(self.transient_prompt.take(), &tstr));
+
         let host = hostname::get().map(|h| h.to_string_lossy().into_owned()).unwrap_or_default();
         let path = env::current_dir().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
 
-        let tmpl = Template::new(&str);
+        let mut templates: Vec<Template> = [Some(&compiled), rcompiled.as_ref(), tcompiled.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(Template::from_compiled)
+            .collect();
+
+        let has_right = rcompiled.is_some();
+        self.prompt = Some(compiled);
+        self.rprompt = rcompiled;
+        self.transient_prompt = tcompiled;
+
+        let truecolor = self.lua.get_config_value::<Option<bool>>("truecolor")?;
+        let cmd_timeout = self.lua.get_config_value::<Option<u64>>("prompt_cmd_timeout")?;
+
+        for tmpl in &mut templates {
+            if let Some(truecolor) = truecolor {
+                tmpl.set_truecolor(truecolor);
+            }
+            tmpl.set_command_timeout(cmd_timeout.filter(|&ms| ms > 0).map(Duration::from_millis));
+        }
+
+        let insert = |key: &'static str, value: String| templates.iter().for_each(|tmpl| tmpl.insert(key, value.clone()));
+
         let envm = EnvManager::new(&path);
         let git_info = git::get_info();
 
-        tmpl.insert("host", host);
-        tmpl.insert("pid", process::id().to_string());
-        tmpl.insert("user", user::get_username().unwrap_or_default());
+        insert("host", host);
+        insert("pid", process::id().to_string());
+        insert("user", user::get_username().unwrap_or_default());
+
+        if let Some((cols, rows)) = self.term_size {
+            insert("cols", cols.to_string());
+            insert("rows", rows.to_string());
+        }
+
+        insert("path", envm.get_self());
+        insert("path-pretty", envm.contract_home());
+        insert("path-folder", envm.pretty_dir());
+        insert("path-short", envm.condensed_path());
 
-        tmpl.insert("path", envm.get_self());
-        tmpl.insert("path-pretty", envm.contract_home());
-        tmpl.insert("path-folder", envm.pretty_dir());
-        tmpl.insert("path-short", envm.condensed_path());
+        let condense_limit: usize = self.lua.get_config_value("path_condense_limit")?;
+        let condense_keep: usize = self.lua.get_config_value("path_condense_keep")?;
+        insert("path-condensed", envm.condensed_path_within(condense_limit, condense_keep));
+
+        let current_dir = PathBuf::from(&path);
+        let git_relative = current_dir.canonicalize().ok().and_then(|dir| git_info.relative_path(&dir));
+        insert("path-git-relative", git_relative.unwrap_or_else(|| envm.condensed_path()));
 
         if git_info.in_repo {
-            tmpl.insert("git.in-repo", true.to_string());
-            tmpl.insert("git.status", git_info.status());
-            tmpl.insert("git.branch", git_info.branch_name);
-            tmpl.insert("git.ahead", git_info.ahead);
-            tmpl.insert("git.behind", git_info.behind);
-            tmpl.insert("git.branch.status", git_info.branch_status);
-            tmpl.insert("git.stash.count", git_info.stash_count);
-
-            tmpl.insert("git.working.display", git_info.working.status_string);
-            tmpl.insert("git.working.deleted", git_info.working.deleted);
-            tmpl.insert("git.working.added", git_info.working.added);
-            tmpl.insert("git.working.modified", git_info.working.modified);
-            tmpl.insert("git.working.untracked", git_info.working.untracked);
-            tmpl.insert("git.working.changed", git_info.working.changed.to_string());
-
-            tmpl.insert("git.staging.display", git_info.staging.status_string);
-            tmpl.insert("git.staging.deleted", git_info.staging.deleted);
-            tmpl.insert("git.staging.added", git_info.staging.added);
-            tmpl.insert("git.staging.modified", git_info.staging.modified);
-            tmpl.insert("git.staging.untracked", git_info.staging.untracked);
-            tmpl.insert("git.staging.changed", git_info.staging.changed.to_string());
-        }
-
-        tmpl.insert(
+            insert("git.in-repo", true.to_string());
+            insert("git.status", git_info.status());
+            insert("git.branch", git_info.branch_name);
+            insert("git.ahead", git_info.ahead);
+            insert("git.behind", git_info.behind);
+            insert("git.branch.status", git_info.branch_status);
+            insert("git.stash.count", git_info.stash_count);
+
+            insert("git.working.display", git_info.working.status_string);
+            insert("git.working.deleted", git_info.working.deleted);
+            insert("git.working.added", git_info.working.added);
+            insert("git.working.modified", git_info.working.modified);
+            insert("git.working.untracked", git_info.working.untracked);
+            insert("git.working.changed", git_info.working.changed.to_string());
+
+            insert("git.staging.display", git_info.staging.status_string);
+            insert("git.staging.deleted", git_info.staging.deleted);
+            insert("git.staging.added", git_info.staging.added);
+            insert("git.staging.modified", git_info.staging.modified);
+            insert("git.staging.untracked", git_info.staging.untracked);
+            insert("git.staging.changed", git_info.staging.changed.to_string());
+        }
+
+        // a job being reaped holds the lock, and the prompt shouldn't wait on it
+        if let Ok(jobs) = crate::JOBS.try_lock() {
+            insert("jobs.count", jobs.active_count().to_string());
+        }
+
+        let last_status = self.last_status.load(Ordering::Relaxed);
+        insert("last.ok", (last_status == 0).to_string());
+        insert("last.code", last_status.to_string());
+
+        insert(
             "prompt",
-            match unsafe { libc::getuid() } {
+            match user::effective_uid() {
                 0 => "#",
                 _ => "%",
             }
             .to_string(),
         );
 
-        Ok(tmpl.render()?)
+        let mut rendered = templates.iter().map(Template::render);
+        let left = rendered.next().transpose()?.unwrap_or_default();
+        let right = if has_right { rendered.next().transpose()? } else { None };
+
+        Ok(Prompts { left, right, transient: rendered.next().transpose()? })
+    }
+
+    // rustyline leaves the cursor below the accepted line, so count the rows the prompt and
+    // input wrapped onto, then replace all of them with the transient prompt and the same input
+    fn draw_transient_prompt(&self, prompt: &str, transient: &str, line: &str) {
+        let Some((cols, _)) = self.term_size.filter(|(cols, _)| *cols > 0) else { return };
+
+        let mut segments: Vec<String> = prompt.split('\n').map(str::to_string).collect();
+        let mut input = line.split('\n');
+        if let Some(last) = segments.last_mut() {
+            last.push_str(input.next().unwrap_or_default());
+        }
+        segments.extend(input.map(|part| format!("> {part}")));
+
+        // a row filled exactly to the edge leaves the cursor wrapped onto the next one
+        let last = segments.len() - 1;
+        let rows: usize = segments
+            .iter()
+            .enumerate()
+            .map(|(idx, segment)| match visible_width(segment) {
+                width if idx == last => width / cols + 1,
+                width => width.max(1).div_ceil(cols),
+            })
+            .sum();
+
+        print!("\x1b[{rows}A\r\x1b[J{transient}{}\n", line.replace('\n', "\n> "));
+        let _ = io::stdout().flush();
+    }
+
+    pub fn hangup_jobs(&self) {
+        if self.lua.get_config_value("huponexit").unwrap_or(true) {
+            crate::JOBS.lock().expect("Able to lock jobs").hangup_all();
+        }
+    }
+
+    // None means the line is not lua and should run as a shell command
+    fn eval_lua_line(&self, line: &str) -> Option<ExitCode> {
+        match self.lua.get_config_value::<Option<String>>("lua_prefix").ok().flatten() {
+            Some(prefix) if !prefix.is_empty() => {
+                let code = line.trim_start().strip_prefix(prefix.as_str())?;
+                Some(self.lua.eval(code).unwrap_or_else(|err| {
+                    eprintln!("lua: {err}");
+                    ExitCode::FAILURE
+                }))
+            }
+            _ => self.lua.eval(&LuaState::transform_lua(line)).ok(),
+        }
+    }
+
+    // the expanded line is echoed like bash, and replaces the `!` form in history before the next prompt
+    fn expand_history(&self, line: String) -> Option<String> {
+        let lua_prefix = self.lua.get_config_value::<Option<String>>("lua_prefix").ok().flatten();
+        if lua_prefix.is_some_and(|prefix| !prefix.is_empty() && line.trim_start().starts_with(prefix.as_str())) {
+            return Some(line);
+        }
+
+        match AsyncLineReader::expand_history(&line) {
+            Ok(None) => Some(line),
+            Ok(Some(expanded)) => {
+                println!("{expanded}");
+                AsyncLineReader::replace_history(&line, expanded.clone());
+                Some(expanded)
+            }
+            Err(err) => {
+                eprintln!("tish: {err}");
+                None
+            }
+        }
     }
 
-    async fn execute_command(&mut self, line: &String) -> ExitCode {
+    async fn execute_command(&self, line: &String) -> ExitCode {
         let mut exit_code = ExitCode::SUCCESS;
         let commands = TishCommand::parse(line);
 
         for cmd in commands {
+            if self.options.xtrace.load(Ordering::Relaxed) {
+                eprintln!("+ {}", cmd.trace_line());
+            }
+
             let result = cmd.execute(self).await;
 
             let err = match result {
-                Ok(_) => continue,
+                Ok(status) => {
+                    exit_code = status;
+                    if self.options.errexit.load(Ordering::Relaxed) && exit_code != ExitCode::SUCCESS {
+                        break;
+                    }
+                    continue;
+                }
                 Err(e) => e,
             };
 
@@ -192,8 +482,13 @@ impl TishShell {
 
             eprintln!("{error_msg}");
             exit_code = ExitCode::FAILURE;
+
+            if self.options.errexit.load(Ordering::Relaxed) {
+                break;
+            }
         }
 
+        self.last_status.store(unsafe { std::mem::transmute::<ExitCode, u8>(exit_code) }, Ordering::Relaxed);
         return exit_code;
     }
 
@@ -201,32 +496,53 @@ impl TishShell {
         let mut status = ExitCode::SUCCESS;
 
         if let Some(line) = self.args.command.to_owned() {
-            let line = alias::resolve_command(line);
-            let fmt_lua = LuaState::transform_lua(&line);
+            let phase = Instant::now();
+            status = self.run_line(&line).await;
+            self.record_phase("command", phase);
+        }
 
-            if let Err(_) = self.lua.eval(&fmt_lua) {
-                status = self.execute_command(&line).await;
-            }
+        // with nothing else to run, commands piped into stdin run like a script instead of the prompt
+        let piped = self.args.command.is_none() && self.args.arguments.is_none() && !self.args.interactive && !stdin_is_tty();
+        if piped {
+            let mut script = String::new();
+            io::stdin().read_to_string(&mut script)?;
+
+            let phase = Instant::now();
+            status = self.run_lines(&script).await;
+            self.record_phase("stdin", phase);
         }
 
-        if self.args.headless {
+        // like sh, -c exits once the command is done unless -i asks for the prompt too
+        if self.args.headless || piped || (self.args.command.is_some() && !self.args.interactive) {
+            self.print_startup_profile();
             let raw_code = unsafe { std::mem::transmute::<ExitCode, u8>(status) };
             process::exit(raw_code as i32);
         }
 
         loop {
-            let prompt = self.format_prompt()?;
+            if self.signal_handler.take_resized() {
+                self.term_size = dimensions();
+            }
+
+            let phase = Instant::now();
+            let Prompts { left, right, transient } = self.format_prompt()?;
+            self.record_phase("first prompt", phase);
+            self.print_startup_profile();
+            self.readline.set_rprompt(right.unwrap_or_default());
+            self.readline.set_completion_ignore_case(self.lua.get_config_value("completion_ignore_case")?);
+            self.readline.set_lua_prefix(self.lua.get_config_value::<Option<String>>("lua_prefix")?.unwrap_or_default());
 
             tokio::select! {
-                readline = self.readline.async_readline(&prompt) => {
+                readline = self.readline.async_readline(&left) => {
                     match readline {
                         Ok(line) => {
-                            let line = alias::resolve_command(line);
-                            let fmt_lua = LuaState::transform_lua(&line);
-
-                            if let Err(_) = self.lua.eval(&fmt_lua) {
-                                self.execute_command(&line).await;
+                            if let Some(transient) = transient.filter(|_| stdout_is_tty()) {
+                                self.draw_transient_prompt(&left, &transient, &line);
                             }
+
+                            let Some(line) = self.expand_history(line) else { continue };
+
+                            self.run_line(&line).await;
                         }
                         Err(ReadlineError::Interrupted) => {
                             self.readline.clear_buffer();
@@ -239,6 +555,8 @@ impl TishShell {
             }
         }
 
+        self.hangup_jobs();
+
         Ok(std::process::ExitCode::SUCCESS)
     }
 }
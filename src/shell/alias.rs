@@ -5,66 +5,73 @@ pub fn resolve_command(line: String) -> String {
         return String::new();
     }
 
-    let mut words: Vec<&str> = line.split_whitespace().collect();
-    if words.is_empty() {
-        return String::new();
-    }
+    // only the first word is resolved, the rest keeps its layout so quoted and heredoc lines survive
+    let line = line.trim();
+    let (first_word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
 
-    let first_word = words[0].to_string();
-    words.remove(0);
+    let resolved = resolve_alias_recursively(first_word.to_string(), Vec::new());
+    let rest = rest.trim_start_matches([' ', '\t']);
 
-    let resolved = resolve_alias_recursively(first_word, Vec::new());
-
-    if !words.is_empty() {
-        format!("{} {}", resolved, words.join(" "))
+    if !rest.is_empty() {
+        format!("{} {}", resolved, rest)
     } else {
         resolved
     }
 }
 
-fn resolve_alias_recursively(command: String, mut accumulated_args: Vec<String>) -> String {
-    let mut seen_aliases = HashSet::new();
-    let mut current_command = command;
+// global aliases replace whole unquoted words anywhere in the line, so `alias -g G='| grep'` can add a pipe
+pub fn resolve_globals(line: &str) -> String {
+    let globals = crate::GLOBAL_ALIASES.lock().expect("Unable to acquire alias lock");
+    // editing aliases shouldn't expand the names being edited
+    if globals.is_empty() || matches!(line.split_whitespace().next(), Some("alias" | "unalias")) {
+        return line.to_string();
+    }
 
-    while !seen_aliases.contains(&current_command) {
-        seen_aliases.insert(current_command.clone());
+    let mut result = String::with_capacity(line.len());
+    let mut word = String::new();
+    let mut quote_char = None;
 
-        let alias = crate::ALIASES.lock().expect("Unable to acquire alias lock");
+    for c in line.chars() {
+        match c {
+            '\'' | '"' if quote_char.is_none() => {
+                quote_char = Some(c);
+                word.push(c);
+            }
+            c if Some(c) == quote_char => {
+                quote_char = None;
+                word.push(c);
+            }
+            c if c.is_whitespace() && quote_char.is_none() => {
+                result.push_str(globals.get(&word).unwrap_or(&word));
+                result.push(c);
+                word.clear();
+            }
+            _ => word.push(c),
+        }
+    }
 
-        match alias.get(&current_command) {
-            Some(resolved) => {
-                let parts: Vec<&str> = resolved.split_whitespace().collect();
-                if parts.is_empty() {
-                    return current_command;
-                }
+    result.push_str(globals.get(&word).unwrap_or(&word));
+    result
+}
 
-                let new_command = parts[0].to_string();
+pub fn resolve_suffix(program: &str) -> Option<String> {
+    let extension = std::path::Path::new(program).extension()?.to_str()?;
+    crate::SUFFIX_ALIASES.lock().expect("Unable to acquire alias lock").get(extension).cloned()
+}
 
-                if parts.len() > 1 {
-                    let new_args: Vec<String> = parts[1..].iter().map(|&s| s.to_string()).collect();
-                    let mut combined_args = new_args;
-                    combined_args.extend(accumulated_args);
-                    accumulated_args = combined_args;
-                }
+// every alias expands at most once, so `alias ls='ls -la'` runs the real ls and `a -> b -> a` stops at the repeated word
+fn resolve_alias_recursively(command: String, mut accumulated_args: Vec<String>) -> String {
+    let aliases = crate::ALIASES.lock().expect("Unable to acquire alias lock");
+    let mut seen_aliases = HashSet::new();
+    let mut current_command = command;
 
-                if new_command == current_command {
-                    if accumulated_args.is_empty() {
-                        return resolved.to_string();
-                    } else {
-                        return format!("{} {}", new_command, accumulated_args.join(" "));
-                    }
-                }
+    while seen_aliases.insert(current_command.clone()) {
+        let Some(resolved) = aliases.get(&current_command) else { break };
+        let mut parts = resolved.split_whitespace();
+        let Some(new_command) = parts.next() else { break };
 
-                current_command = new_command;
-            }
-            None => {
-                if accumulated_args.is_empty() {
-                    return current_command;
-                } else {
-                    return format!("{} {}", current_command, accumulated_args.join(" "));
-                }
-            }
-        }
+        accumulated_args.splice(0..0, parts.map(str::to_string));
+        current_command = new_command.to_string();
     }
 
     if accumulated_args.is_empty() {
@@ -73,3 +80,24 @@ fn resolve_alias_recursively(command: String, mut accumulated_args: Vec<String>)
         format!("{} {}", current_command, accumulated_args.join(" "))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn define(name: &str, value: &str) { crate::ALIASES.lock().expect("Able to lock aliases").insert(name.to_string(), value.to_string()); }
+
+    #[test]
+    fn self_referential_alias_expands_once() {
+        define("tish_self", "tish_self -la");
+        assert_eq!(resolve_command("tish_self src".to_string()), "tish_self -la src");
+    }
+
+    #[test]
+    fn mutually_referential_aliases_stop_at_repeat() {
+        define("tish_ping", "tish_pong -x");
+        define("tish_pong", "tish_ping -y");
+        assert_eq!(resolve_command("tish_ping".to_string()), "tish_ping -y -x");
+        assert_eq!(resolve_command("tish_pong a".to_string()), "tish_pong -x -y a");
+    }
+}
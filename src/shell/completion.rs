@@ -0,0 +1,89 @@
+use mlua::Function as LuaFunction;
+use std::cmp::Reverse;
+
+#[derive(Clone)]
+pub enum CompletionSpec {
+    Words(Vec<String>),
+    Lua(LuaFunction),
+}
+
+const GIT_SUBCOMMANDS: &[&str] = &[
+    "add", "bisect", "blame", "branch", "checkout", "cherry-pick", "clean", "clone", "commit", "config", "diff", "fetch", "grep", "init", "log", "merge", "mv", "pull", "push", "rebase", "reflog",
+    "remote", "reset", "restore", "revert", "rm", "show", "stash", "status", "switch", "tag", "worktree",
+];
+
+const CARGO_SUBCOMMANDS: &[&str] = &[
+    "add", "bench", "build", "check", "clean", "clippy", "doc", "fetch", "fmt", "init", "install", "metadata", "new", "publish", "remove", "run", "search", "test", "tree", "uninstall", "update",
+];
+
+fn builtin(command: &str) -> Option<&'static [&'static str]> {
+    match command {
+        "git" => Some(GIT_SUBCOMMANDS),
+        "cargo" => Some(CARGO_SUBCOMMANDS),
+        _ => None,
+    }
+}
+
+// `words` holds the command and every word after it, the last one being the word under the cursor.
+// lists and built-in specs only complete the first argument, a lua spec decides for itself from the words.
+// None falls back to filename completion, and so does a spec that errors or has nothing matching
+pub fn complete(words: &[String]) -> Option<Vec<String>> {
+    let (current, previous) = words.split_last()?;
+    let command = previous.first()?;
+    let spec = crate::COMPLETIONS.lock().expect("Able to lock completions").get(command).cloned();
+
+    let mut candidates = match spec {
+        Some(CompletionSpec::Lua(func)) => func.call::<Vec<String>>(words.to_vec()).ok()?,
+        Some(CompletionSpec::Words(words)) if previous.len() == 1 => words,
+        None if previous.len() == 1 => builtin(command)?.iter().map(ToString::to_string).collect(),
+        _ => return None,
+    };
+
+    candidates.retain(|candidate| fuzzy_score(candidate, current).is_some());
+    rank(&mut candidates, current, false);
+
+    Some(candidates).filter(|candidates| !candidates.is_empty())
+}
+
+// None unless every pattern char appears in order. prefixes rank first, then runs of consecutive
+// chars and matches at the start of a word (`gco` on `git-checkout`), and shorter candidates break ties
+pub fn fuzzy_score(candidate: &str, pattern: &str) -> Option<i64> {
+    let chars: Vec<char> = candidate.chars().collect();
+    let mut score = if candidate.starts_with(pattern) { 100 } else { 0 };
+    let mut idx = 0;
+    let mut previous = None;
+
+    for wanted in pattern.chars() {
+        while *chars.get(idx)? != wanted {
+            idx += 1;
+        }
+
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == idx) {
+            score += 5;
+        }
+        if idx == 0 || matches!(chars[idx - 1], '-' | '_' | '.' | '/' | ' ') {
+            score += 3;
+        }
+
+        previous = Some(idx);
+        idx += 1;
+    }
+
+    Some(score - chars.len() as i64 / 4)
+}
+
+// best matches first, keeping the alphabetical order between equal scores
+pub fn rank(candidates: &mut Vec<String>, pattern: &str, ignore_case: bool) {
+    candidates.sort();
+    candidates.dedup();
+
+    let pattern = if ignore_case { pattern.to_lowercase() } else { pattern.to_string() };
+    candidates.sort_by_cached_key(|candidate| {
+        let score = match ignore_case {
+            true => fuzzy_score(&candidate.to_lowercase(), &pattern),
+            false => fuzzy_score(candidate, &pattern),
+        };
+        Reverse(score.unwrap_or(i64::MIN))
+    });
+}
@@ -1,4 +1,9 @@
-use git2::{Repository, StatusOptions};
+use git2::{Repository, Status, StatusOptions};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
 pub struct GitStatusInfo {
     pub changed: bool,
@@ -22,6 +27,31 @@ impl Default for GitStatusInfo {
     }
 }
 
+pub struct FileStatus {
+    pub letter: char,
+    pub staged: bool,
+}
+
+impl FileStatus {
+    // the colors the prompt's git status uses, green once staged and yellow for work tree changes
+    pub fn color(&self) -> &'static str {
+        match self.staged {
+            true => "\x1b[32m",
+            false => "\x1b[33m",
+        }
+    }
+
+    // conflicts outrank staged changes, which outrank work tree changes and then untracked files
+    fn precedence(&self) -> u8 {
+        match self.letter {
+            'U' => 3,
+            _ if self.staged => 2,
+            '?' => 0,
+            _ => 1,
+        }
+    }
+}
+
 pub struct GitInfo {
     pub in_repo: bool,
     pub working: GitStatusInfo,
@@ -31,6 +61,7 @@ pub struct GitInfo {
     pub stash_count: String,
     pub branch_status: String,
     pub branch_name: String,
+    pub workdir: Option<PathBuf>,
 }
 
 impl Default for GitInfo {
@@ -44,6 +75,7 @@ impl Default for GitInfo {
             stash_count: String::new(),
             branch_status: String::new(),
             branch_name: String::new(),
+            workdir: None,
         }
     }
 }
@@ -72,6 +104,78 @@ impl GitInfo {
     }
 }
 
+impl GitInfo {
+    // `repo/sub/dir` for a path inside the work tree, None outside of it
+    pub fn relative_path(&self, path: &Path) -> Option<String> {
+        let workdir = self.workdir.as_ref()?;
+        let relative = path.strip_prefix(workdir).ok()?;
+        let repo_name = workdir.file_name()?.to_string_lossy();
+
+        match relative.as_os_str().is_empty() {
+            true => Some(repo_name.into_owned()),
+            false => Some(format!("{}/{}", repo_name, relative.display())),
+        }
+    }
+}
+
+fn get_workdir(repo: &Repository) -> Option<PathBuf> {
+    let workdir = repo.workdir()?;
+    Some(workdir.canonicalize().unwrap_or_else(|_| workdir.to_path_buf()))
+}
+
+// the letter `git status --short` shows, staged changes first. None for a clean file
+fn file_status(status: Status) -> Option<FileStatus> {
+    let (letter, staged) = match status {
+        s if s.is_conflicted() => ('U', false),
+        s if s.is_index_new() => ('A', true),
+        s if s.is_index_modified() || s.is_index_typechange() => ('M', true),
+        s if s.is_index_deleted() => ('D', true),
+        s if s.is_index_renamed() => ('R', true),
+        s if s.is_wt_new() => ('?', false),
+        s if s.is_wt_modified() || s.is_wt_typechange() => ('M', false),
+        s if s.is_wt_deleted() => ('D', false),
+        s if s.is_wt_renamed() => ('R', false),
+        _ => return None,
+    };
+
+    Some(FileStatus { letter, staged })
+}
+
+// statuses of the entries directly inside `dir`, keyed by file name. a directory shows the most pressing
+// status of the paths below it. None outside of a repository
+pub fn dir_statuses(dir: &Path) -> Option<HashMap<String, FileStatus>> {
+    let repo = Repository::discover(dir).ok()?;
+    let workdir = get_workdir(&repo)?;
+    let dir = fs::canonicalize(dir).ok()?;
+    let relative = dir.strip_prefix(&workdir).ok()?;
+
+    let statuses = repo
+        .statuses(Some(
+            StatusOptions::new()
+                .include_untracked(true)
+                .include_ignored(false)
+                .renames_head_to_index(true)
+                .renames_index_to_workdir(true),
+        ))
+        .ok()?;
+
+    let mut result = HashMap::new();
+    for entry in statuses.iter() {
+        let Some(path) = entry.path() else { continue };
+        let Ok(inside) = Path::new(path).strip_prefix(relative) else { continue };
+        let Some(name) = inside.components().next() else { continue };
+
+        let Some(status) = file_status(entry.status()) else { continue };
+        let name = name.as_os_str().to_string_lossy().into_owned();
+
+        if result.get(&name).is_none_or(|current: &FileStatus| status.precedence() > current.precedence()) {
+            result.insert(name, status);
+        }
+    }
+
+    Some(result)
+}
+
 fn get_branch_name(repo: &Repository) -> String {
     if let Ok(head) = repo.head() {
         if head.is_branch() {
@@ -126,6 +230,7 @@ pub fn get_info() -> GitInfo {
 
     git_info.in_repo = true;
     git_info.branch_name = get_branch_name(&repo);
+    git_info.workdir = get_workdir(&repo);
 
     let mut working_status = GitStatusInfo::default();
     let mut staging_status = GitStatusInfo::default();
@@ -1,6 +1,8 @@
 // TODO: Highlight variables after the initial $
 // like $HOME/path should all be green
 
+use crate::os::env::EnvManager;
+
 use std::{
     collections::HashMap,
     env,
@@ -21,9 +23,31 @@ pub enum TokenType {
     ImplicitDirectory,
     Operator,
     Comment,
+    Keyword,
     Unknown,
 }
 
+impl TokenType {
+    // the keys of config.colors
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "valid_command" => Some(Self::ValidCommand),
+            "invalid_command" => Some(Self::InvalidCommand),
+            "argument" => Some(Self::Argument),
+            "option" => Some(Self::Option),
+            "variable" => Some(Self::Variable),
+            "string" => Some(Self::String),
+            "number" => Some(Self::Number),
+            "directory" => Some(Self::Directory),
+            "implicit_directory" => Some(Self::ImplicitDirectory),
+            "operator" => Some(Self::Operator),
+            "comment" => Some(Self::Comment),
+            "keyword" => Some(Self::Keyword),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Token {
     pub token_type: TokenType,
@@ -50,13 +74,16 @@ impl Highlighter {
         styles.insert(TokenType::Number, "\x1b[34m".to_string());
         styles.insert(TokenType::Operator, "\x1b[37m".to_string());
         styles.insert(TokenType::Comment, "\x1b[90m".to_string());
+        styles.insert(TokenType::Keyword, "\x1b[35m".to_string());
         styles.insert(TokenType::Unknown, "\x1b[0m".to_string());
 
         Self { styles }
     }
 
+    pub fn set_style(&mut self, token_type: TokenType, style: String) { self.styles.insert(token_type, style); }
+
     pub fn command_exists(&self, command: &str) -> bool {
-        if matches!(command, "cd" | "ls" | "exit" | "help" | "?" | "source" | "echo" | "tish") {
+        if matches!(command, "cd" | "ls" | "tree" | "dirs" | "pushd" | "popd" | "exit" | "help" | "?" | "source" | "echo" | "tish" | "fg" | "bg" | "jobs" | "kill" | "disown" | "history" | "alias" | "unalias" | "set" | ".") {
             return true;
         }
 
@@ -84,13 +111,16 @@ impl Highlighter {
         false
     }
 
-    pub fn highlight_with_cache(&self, input: &str, command_cache: &HashMap<String, bool>) -> String {
+    pub fn highlight_with_cache(&self, input: &str, command_cache: &HashMap<String, bool>, lua_prefix: &str) -> String {
         let input = input.trim_end();
         if input.is_empty() {
             return String::new();
         }
 
-        let tokens = self.tokenize(input, command_cache);
+        let tokens = match Self::is_lua_line(input, lua_prefix) {
+            true => self.tokenize_lua(input),
+            false => self.tokenize(input, command_cache),
+        };
         let mut result = String::new();
         let mut last_end = 0;
 
@@ -117,13 +147,95 @@ impl Highlighter {
         result
     }
 
+    // lines that `TishShell::run` will hand to lua rather than the shell. with a prefix set only prefixed lines are lua
+    fn is_lua_line(input: &str, lua_prefix: &str) -> bool {
+        let trimmed = input.trim_start();
+        if !lua_prefix.is_empty() {
+            return trimmed.starts_with(lua_prefix);
+        }
+
+        let word_end = trimmed.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(trimmed.len());
+        let word = &trimmed[..word_end];
+
+        match word {
+            "local" | "function" | "require" => true,
+            "" => false,
+            _ => (*crate::LUA_FN).contains(word) && trimmed[word_end..].trim_start().starts_with(['(', '.', ':', '"', '\'', '{']),
+        }
+    }
+
+    fn tokenize_lua(&self, input: &str) -> Vec<Token> {
+        const KEYWORDS: &[&str] = &[
+            "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in", "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+        ];
+
+        let mut tokens = Vec::new();
+        let mut chars = input.char_indices().peekable();
+
+        while let Some((start, c)) = chars.next() {
+            let mut end = start + c.len_utf8();
+
+            let token_type = match c {
+                '-' if chars.peek().is_some_and(|&(_, next)| next == '-') => {
+                    end = input.len();
+                    while chars.next().is_some() {}
+                    TokenType::Comment
+                }
+                '"' | '\'' => {
+                    let mut escaped = false;
+                    while let Some((pos, next_c)) = chars.next() {
+                        end = pos + next_c.len_utf8();
+                        if !escaped && next_c == c {
+                            break;
+                        }
+                        escaped = next_c == '\\' && !escaped;
+                    }
+                    TokenType::String
+                }
+                c if c.is_ascii_digit() => {
+                    while let Some(&(pos, next_c)) = chars.peek() {
+                        if !next_c.is_ascii_alphanumeric() && next_c != '.' {
+                            break;
+                        }
+                        end = pos + next_c.len_utf8();
+                        chars.next();
+                    }
+                    TokenType::Number
+                }
+                c if c.is_alphabetic() || c == '_' => {
+                    while let Some(&(pos, next_c)) = chars.peek() {
+                        if !next_c.is_alphanumeric() && next_c != '_' {
+                            break;
+                        }
+                        end = pos + next_c.len_utf8();
+                        chars.next();
+                    }
+
+                    match &input[start..end] {
+                        word if KEYWORDS.contains(&word) => TokenType::Keyword,
+                        word if (*crate::LUA_FN).contains(word) => TokenType::ValidCommand,
+                        _ => TokenType::Argument,
+                    }
+                }
+                '=' | '<' | '>' | '~' | '+' | '-' | '*' | '/' | '%' | '^' | '#' | '.' | ',' | ':' | ';' | '(' | ')' | '[' | ']' | '{' | '}' => TokenType::Operator,
+                _ => continue,
+            };
+
+            tokens.push(Token {
+                token_type,
+                start,
+                end,
+                content: input[start..end].to_string(),
+            });
+        }
+
+        tokens
+    }
+
     fn expand_path(&self, path: &Path) -> Option<PathBuf> {
         let path_str = path.to_string_lossy();
-        if path_str.starts_with("~/") {
-            if let Some(home_dir) = dirs::home_dir() {
-                let relative_path = path_str.strip_prefix("~/").unwrap_or(&path_str);
-                return Some(home_dir.join(relative_path));
-            }
+        if path_str.starts_with('~') {
+            return Some(PathBuf::from(EnvManager::expand_tilde(&path_str)));
         } else if path.is_relative() {
             if let Ok(current_dir) = std::env::current_dir() {
                 return Some(current_dir.join(path));
@@ -152,6 +264,53 @@ impl Highlighter {
         false
     }
 
+    fn tokenize_double_quoted(input: &str, start: usize, end: usize) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut text_start = start;
+        let mut escaped = false;
+        let mut chars = input[start..end].char_indices().map(|(i, c)| (start + i, c)).peekable();
+
+        let push = |tokens: &mut Vec<Token>, token_type: TokenType, from: usize, to: usize| {
+            if from < to {
+                tokens.push(Token {
+                    token_type,
+                    start: from,
+                    end: to,
+                    content: input[from..to].to_string(),
+                });
+            }
+        };
+
+        while let Some((pos, c)) = chars.next() {
+            if escaped || c != '$' {
+                escaped = c == '\\' && !escaped;
+                continue;
+            }
+
+            let braced = chars.next_if(|&(_, next)| next == '{').is_some();
+            let mut var_end = pos + 1 + braced as usize;
+
+            while let Some((next_pos, next_c)) = chars.next_if(|&(_, next)| next.is_alphanumeric() || next == '_') {
+                var_end = next_pos + next_c.len_utf8();
+            }
+
+            if braced {
+                if let Some((close_pos, _)) = chars.next_if(|&(_, next)| next == '}') {
+                    var_end = close_pos + 1;
+                }
+            }
+
+            if var_end > pos + 1 {
+                push(&mut tokens, TokenType::String, text_start, pos);
+                push(&mut tokens, TokenType::Variable, pos, var_end);
+                text_start = var_end;
+            }
+        }
+
+        push(&mut tokens, TokenType::String, text_start, end);
+        tokens
+    }
+
     fn tokenize(&self, input: &str, command_cache: &HashMap<String, bool>) -> Vec<Token> {
         let mut tokens = Vec::new();
         let mut is_first_word = true;
@@ -202,23 +361,26 @@ impl Highlighter {
                 '"' | '\'' => {
                     let quote = c;
                     let start = start_pos;
-                    let mut content = String::from(c);
                     let mut escaped = false;
-                    let mut end = start;
+                    let mut end = start + c.len_utf8();
                     while let Some((pos, next_c)) = chars.next() {
-                        content.push(next_c);
-                        end = pos;
+                        end = pos + next_c.len_utf8();
                         if !escaped && next_c == quote {
                             break;
                         }
                         escaped = next_c == '\\' && !escaped;
                     }
-                    tokens.push(Token {
-                        token_type: TokenType::String,
-                        start,
-                        end: end + 1,
-                        content,
-                    });
+
+                    // single quotes are literal, double quotes still expand variables
+                    match quote {
+                        '"' => tokens.extend(Self::tokenize_double_quoted(input, start, end)),
+                        _ => tokens.push(Token {
+                            token_type: TokenType::String,
+                            start,
+                            end,
+                            content: input[start..end].to_string(),
+                        }),
+                    }
                     is_first_word = false;
                 }
                 '-' if !in_whitespace => {
@@ -291,7 +453,7 @@ impl Highlighter {
                     let mut end = start;
 
                     while let Some(&(pos, next_c)) = chars.peek() {
-                        if next_c.is_whitespace() || next_c == '\\' {
+                        if next_c.is_whitespace() || matches!(next_c, '\\' | '|' | '&' | ';') {
                             break;
                         }
                         content.push(next_c);
@@ -300,7 +462,9 @@ impl Highlighter {
                     }
 
                     let token_type = if is_first_word {
-                        if self.can_be_implicit_cd(&content, true) {
+                        let is_command = !content.contains('/') && command_cache.get(&content).copied().unwrap_or(false);
+
+                        if !is_command && self.can_be_implicit_cd(&content, true) {
                             TokenType::ImplicitDirectory
                         } else if content.starts_with("./") || content.starts_with("../") {
                             let path = Path::new(&content);
@@ -338,9 +502,6 @@ impl Highlighter {
 
                     if let Some(&(pos, next_c)) = chars.peek() {
                         match (c, next_c) {
-                            // FIXME
-                            // if ls && ls (green)
-                            // if anything_else && ls (its red)
                             ('&', '&') | ('|', '|') | ('>', '>') | ('<', '<') => {
                                 content.push(next_c);
                                 end = pos;
@@ -357,7 +518,7 @@ impl Highlighter {
                         content,
                     });
 
-                    is_first_word = true;
+                    is_first_word = matches!(c, '|' | '&' | ';');
                     in_whitespace = true;
                 }
                 _ => {}
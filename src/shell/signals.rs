@@ -1,14 +1,18 @@
-use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, OnceLock};
 use tokio::process::Child;
 
 pub const SIGTSTP: i32 = 20;
 pub const SIGCONT: i32 = 18;
 pub const SIGINT: i32 = 2;
+pub const SIGWINCH: i32 = 28;
 
 pub(crate) static CURRENT_FOREGROUND_PID: AtomicI32 = AtomicI32::new(-1);
 pub(crate) static GLOBAL_SIGNAL_HANDLER: OnceLock<Arc<SignalHandler>> = OnceLock::new();
 
+static TERMINAL_RESIZED: AtomicBool = AtomicBool::new(false);
+static PREVIOUS_WINCH_HANDLER: AtomicUsize = AtomicUsize::new(libc::SIG_DFL);
+
 #[derive(Clone)]
 pub struct SignalHandler {
     pub foreground_info: Arc<Mutex<Option<(String, Vec<String>)>>>,
@@ -27,6 +31,9 @@ impl SignalHandler {
             libc::signal(SIGTSTP, handle_tstp as libc::sighandler_t);
             libc::signal(SIGCONT, handle_cont as libc::sighandler_t);
             libc::signal(SIGINT, handle_int as libc::sighandler_t);
+
+            let previous = libc::signal(SIGWINCH, handle_winch as extern "C" fn(libc::c_int) as libc::sighandler_t);
+            PREVIOUS_WINCH_HANDLER.store(previous, Ordering::SeqCst);
         }
 
         handler
@@ -41,6 +48,9 @@ impl SignalHandler {
         }
     }
 
+    // true once per resize, the flag is only set by the handler so the main loop does the real work
+    pub fn take_resized(&self) -> bool { TERMINAL_RESIZED.swap(false, Ordering::SeqCst) }
+
     pub async fn clear_foreground_process(&self) {
         CURRENT_FOREGROUND_PID.store(-1, Ordering::SeqCst);
 
@@ -88,6 +98,17 @@ extern "C" fn handle_int(_: libc::c_int) {
     }
 }
 
+// rustyline installs its own SIGWINCH handler to redraw the line being edited, so chain to it
+extern "C" fn handle_winch(sig: libc::c_int) {
+    TERMINAL_RESIZED.store(true, Ordering::SeqCst);
+
+    let previous = PREVIOUS_WINCH_HANDLER.load(Ordering::SeqCst);
+    if previous != libc::SIG_DFL && previous != libc::SIG_IGN && previous != libc::SIG_ERR {
+        let handler: extern "C" fn(libc::c_int) = unsafe { std::mem::transmute(previous) };
+        handler(sig);
+    }
+}
+
 pub extern "C" fn handle_cont(_: libc::c_int) {
     unsafe {
         let pid = CURRENT_FOREGROUND_PID.load(Ordering::SeqCst);
@@ -1,9 +1,18 @@
+use std::ops::Range;
+
 #[derive(Clone, Debug)]
 pub struct Tokenizer {
     current: Option<String>,
     has_redirection: bool,
 }
 
+pub struct Heredoc {
+    body: String,
+    expand: bool,
+    pub position: usize,
+    pub terminated: bool,
+}
+
 impl Tokenizer {
     pub fn new(line: &str) -> Self {
         let has_redirection = line.contains(" > ") || line.contains(" < ");
@@ -41,21 +50,49 @@ impl Tokenizer {
         args
     }
 
-    pub fn peek(&self) -> String {
-        let mut res = String::new();
-        if let Some(cur) = self.current.as_deref() {
-            let mut open = 0u8;
-            for c in cur.chars().into_iter() {
-                if c.eq(&'"') || c.eq(&'\'') {
-                    open = open ^ 1;
-                } else if c.eq(&' ') && open == 0 {
-                    break;
-                } else {
-                    res.push(c);
-                }
+    pub fn peek(&self) -> String { self.clone().next().unwrap_or_default() }
+
+    pub fn has_open_quote(line: &str) -> bool {
+        let mut quote_char = None;
+        let mut escaped = false;
+        let mut in_comment = false;
+        let mut prev = None;
+
+        for c in line.chars() {
+            match c {
+                '\n' if in_comment => in_comment = false,
+                _ if in_comment => {}
+                _ if escaped => escaped = false,
+                '\\' if quote_char != Some('\'') => escaped = true,
+                '\'' | '"' if quote_char.is_none() => quote_char = Some(c),
+                c if Some(c) == quote_char => quote_char = None,
+                '#' if quote_char.is_none() && prev.is_none_or(char::is_whitespace) => in_comment = true,
+                _ => {}
+            }
+            prev = Some(c);
+        }
+
+        quote_char.is_some()
+    }
+
+    // byte offset where the last word starts, a quoted or escaped space doesn't end a word
+    pub fn last_word_start(line: &str) -> usize {
+        let mut start = 0;
+        let mut quote_char = None;
+        let mut escaped = false;
+
+        for (i, c) in line.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quote_char != Some('\'') => escaped = true,
+                '\'' | '"' if quote_char.is_none() => quote_char = Some(c),
+                c if Some(c) == quote_char => quote_char = None,
+                c if c.is_whitespace() && quote_char.is_none() => start = i + c.len_utf8(),
+                _ => {}
             }
         }
-        res
+
+        start
     }
 
     pub fn has_redirection(&self) -> bool {
@@ -70,36 +107,160 @@ impl Tokenizer {
 impl Iterator for Tokenizer {
     type Item = String;
 
+    // quotes group words and are removed, a backslash escapes whitespace, quotes and itself
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(current) = self.current.take() {
-            let mut stop = usize::MAX;
-            let mut nxt = String::new();
-            let mut remainder = String::new();
-            let mut open = 0u8;
-
-            for (i, c) in current.chars().enumerate() {
-                if c == '"' || c == '\'' {
-                    open ^= 1;
-                } else if c == ' ' && open == 0 {
-                    stop = i + 1;
+        let current = self.current.take()?;
+        let current = current.trim_start();
+
+        if current.is_empty() {
+            return None;
+        }
+
+        let mut token = String::new();
+        let mut quote_char = None;
+        let mut stop = current.len();
+        let mut chars = current.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            match (c, quote_char) {
+                ('\\', Some('\'')) => token.push(c),
+                ('\\', quote) => match chars.peek() {
+                    Some(&(_, next)) if next == '"' || next == '\\' || (quote.is_none() && (next == '\'' || next.is_whitespace())) => {
+                        token.push(next);
+                        chars.next();
+                    }
+                    _ => token.push(c),
+                },
+                ('\'' | '"', None) => quote_char = Some(c),
+                (c, Some(q)) if c == q => quote_char = None,
+                (c, None) if c.is_whitespace() => {
+                    stop = i;
                     break;
-                } else {
-                    nxt.push(c);
                 }
+                _ => token.push(c),
             }
+        }
+
+        let remainder = &current[stop..];
+        if !remainder.trim_start().is_empty() {
+            self.current = Some(remainder.to_string());
+        }
+
+        Some(token)
+    }
+}
 
-            if stop < current.len() {
-                remainder = current[stop..].to_string();
+impl Heredoc {
+    // splits `cmd <<EOF` and the lines up to `EOF` into the command line and the heredoc body
+    pub fn split(input: &str) -> Option<(String, Self)> {
+        let (first_line, rest) = input.split_once('\n').unwrap_or((input, ""));
+        let (range, delimiter, strip_tabs, quoted) = Self::find_operator(first_line)?;
+
+        let mut body = String::new();
+        let mut terminated = false;
+        let mut lines = rest.split('\n');
+
+        for line in lines.by_ref() {
+            let line = if strip_tabs { line.trim_start_matches('\t') } else { line };
+            if line == delimiter {
+                terminated = true;
+                break;
             }
+            body.push_str(line);
+            body.push('\n');
+        }
+
+        let mut command = format!("{}{}", &first_line[..range.start], &first_line[range.end..]);
+        for line in lines {
+            command.push('\n');
+            command.push_str(line);
+        }
+
+        Some((
+            command,
+            Self {
+                body,
+                expand: !quoted,
+                position: range.start,
+                terminated,
+            },
+        ))
+    }
+
+    // `$VAR` and `${VAR}` are expanded unless the delimiter was quoted, `\$` stays literal
+    pub fn contents(&self) -> String {
+        if !self.expand {
+            return self.body.clone();
+        }
+
+        let mut result = String::with_capacity(self.body.len());
+        let mut chars = self.body.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&'$') => result.extend(chars.next()),
+                '$' => {
+                    let braced = chars.next_if_eq(&'{').is_some();
+                    let mut name = String::new();
 
-            if !remainder.is_empty() {
-                self.current = Some(remainder);
+                    while let Some(c) = chars.next_if(|&c| c.is_alphanumeric() || c == '_') {
+                        name.push(c);
+                    }
+
+                    if braced && chars.next_if_eq(&'}').is_none() {
+                        result.push_str("${");
+                        result.push_str(&name);
+                    } else if name.is_empty() {
+                        result.push('$');
+                        if braced {
+                            result.push_str("{}");
+                        }
+                    } else {
+                        result.push_str(&std::env::var(&name).unwrap_or_default());
+                    }
+                }
+                _ => result.push(c),
             }
+        }
+
+        result
+    }
+
+    fn find_operator(line: &str) -> Option<(Range<usize>, String, bool, bool)> {
+        let mut quote_char = None;
 
-            if !nxt.is_empty() {
-                return Some(nxt);
+        for (i, c) in line.char_indices() {
+            match c {
+                '\'' | '"' if quote_char.is_none() => quote_char = Some(c),
+                c if Some(c) == quote_char => quote_char = None,
+                '<' if quote_char.is_none() && line[i..].starts_with("<<") && !line[i..].starts_with("<<<") => {
+                    let rest = &line[i + 2..];
+                    let strip_tabs = rest.starts_with('-');
+                    let rest = rest.strip_prefix('-').unwrap_or(rest);
+                    let word = rest.trim_start();
+                    let word_start = line.len() - word.len();
+
+                    let (delimiter, quoted, word_len) = match word.chars().next() {
+                        Some(quote @ ('\'' | '"')) => {
+                            let close = word[1..].find(quote)? + 1;
+                            (word[1..close].to_string(), true, close + 1)
+                        }
+                        _ => {
+                            let len = word.find(|c: char| c.is_whitespace() || matches!(c, ';' | '|' | '&' | '<' | '>')).unwrap_or(word.len());
+                            (word[..len].to_string(), false, len)
+                        }
+                    };
+
+                    if delimiter.is_empty() {
+                        return None;
+                    }
+
+                    return Some((i..word_start + word_len, delimiter, strip_tabs, quoted));
+                }
+                _ => {}
             }
         }
+
         None
     }
 }
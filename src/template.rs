@@ -1,3 +1,4 @@
+use crate::{lazy_lock, os::env::EnvManager, shell::tokenizer::Tokenizer};
 use anyhow::{anyhow, Error};
 use regex::Regex;
 use serde::Deserialize;
@@ -6,9 +7,14 @@ use std::{
     cell::RefCell,
     collections::{HashMap, HashSet},
     env,
+    io::Read,
     iter::Peekable,
-    process::Command,
+    path::PathBuf,
+    process::{Command, ExitStatus, Stdio},
     str::Chars,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 #[derive(Debug, Clone)]
@@ -80,6 +86,7 @@ enum TemplateToken {
 
     Partial {
         path: String,
+        args: Vec<(String, TemplateToken)>,
     },
 
     Repeat {
@@ -135,6 +142,8 @@ enum Operator {
     StartsWith,
     EndsWith,
     Matches,
+    MatchesIgnoreCase,
+    MatchesAny,
 
     // collection operations
     In,
@@ -152,6 +161,7 @@ enum Operator {
     GreaterEquals,
     Less,
     LessEquals,
+    Between,
 
     // type checks
     IsNumber,
@@ -170,6 +180,8 @@ impl Operator {
             "starts_with" => Some(StartsWith),
             "ends_with" => Some(EndsWith),
             "matches" => Some(Matches),
+            "matches_i" => Some(MatchesIgnoreCase),
+            "matches_any" => Some(MatchesAny),
             "in" => Some(In),
             "not_in" => Some(NotIn),
             "is_empty" => Some(IsEmpty),
@@ -181,6 +193,7 @@ impl Operator {
             "greater_equals" | ">=" => Some(GreaterEquals),
             "less" | "<" => Some(Less),
             "less_equals" | "<=" => Some(LessEquals),
+            "between" => Some(Between),
             "is_number" => Some(IsNumber),
             "is_integer" => Some(IsInteger),
             _ => None,
@@ -202,6 +215,8 @@ impl Operator {
             "starts_with",
             "ends_with",
             "matches",
+            "matches_i",
+            "matches_any",
             "in",
             "not_in",
             "is_empty",
@@ -217,6 +232,7 @@ impl Operator {
             "<",
             "less_equals",
             "<=",
+            "between",
             "is_number",
             "is_integer",
         ]
@@ -240,6 +256,7 @@ enum ConditionType {
 #[derive(Debug, Clone)]
 enum OperationParam {
     Index(usize),
+    Name(String),
     ReplaceStr(String),
 }
 
@@ -255,9 +272,30 @@ enum StringOperationType {
     Match,
     Split,
     Replace,
+    RegexReplace,
+    Length,
+    Substr,
+    Truncate,
+    PadLeft,
+    PadRight,
+    Abbreviate,
     DefaultValue,
 }
 
+// operations called with arguments, both on their own as `{name(...)}` and piped as `| name(...)`
+const STRING_OPERATIONS: &[(&str, StringOperationType)] = &[
+    ("match", StringOperationType::Match),
+    ("split", StringOperationType::Split),
+    ("replace", StringOperationType::Replace),
+    ("regex_replace", StringOperationType::RegexReplace),
+    ("substr", StringOperationType::Substr),
+    ("slice", StringOperationType::Substr),
+    ("truncate", StringOperationType::Truncate),
+    ("pad_left", StringOperationType::PadLeft),
+    ("pad_right", StringOperationType::PadRight),
+    ("abbreviate", StringOperationType::Abbreviate),
+];
+
 #[derive(Debug, Clone)]
 struct Operation {
     operation_type: StringOperationType,
@@ -305,6 +343,14 @@ impl<'c> ScopedContext<'c> {
         }
     }
 
+    fn child(&'c self) -> ScopedContext<'c> {
+        ScopedContext {
+            variables: HashMap::new(),
+            constants: HashSet::new(),
+            parent: Some(self),
+        }
+    }
+
     fn get(&self, key: &str) -> Option<String> {
         if let Some(value) = self.variables.get(key) {
             Some(value.clone())
@@ -337,12 +383,34 @@ impl<'c> ScopedContext<'c> {
 }
 
 type State<'c> = (ScopedContext<'c>, PendingUpdates);
+type PartialCache = HashMap<PathBuf, (SystemTime, Arc<Vec<TemplateToken>>)>;
+
+lazy_lock! {
+    static PARTIAL_CACHE: Mutex<PartialCache> = Mutex::new(PartialCache::new());
+}
+
+#[derive(Clone)]
+pub struct CompiledTemplate {
+    source: String,
+    tokens: Arc<Vec<TemplateToken>>,
+}
+
+impl CompiledTemplate {
+    pub fn source(&self) -> &str { &self.source }
+}
 
 pub struct Template<'c> {
-    template: String,
+    source: String,
+    tokens: Arc<Vec<TemplateToken>>,
     state: RefCell<State<'c>>,
+    styles: RefCell<Vec<String>>,
+    truecolor: bool,
+    command_timeout: Option<Duration>,
 }
 
+// a range loop renders on every prompt, so anything longer is left unexpanded rather than allocated
+const MAX_RANGE_LEN: f64 = 10_000.0;
+
 const ANSI_RESET: &str = "\x1b[0m";
 const ANSI_BOLD: &str = "\x1b[1m";
 const ANSI_ITALIC: &str = "\x1b[3m";
@@ -384,11 +452,85 @@ const ANSI_COLORS: &[(&str, &str)] = &[
     ("on_bright_white", "\x1b[107m"),
 ];
 
+// xterm defaults for the 16 basic colors, in sgr order (30-37, then 90-97)
+const ANSI_PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
 impl<'c> Template<'c> {
     pub fn new(template: &str) -> Self {
+        let mut tmpl = Self {
+            source: template.to_string(),
+            tokens: Arc::new(Vec::new()),
+            state: RefCell::new((ScopedContext::new(), PendingUpdates::new())),
+            styles: RefCell::new(Vec::new()),
+            truecolor: Self::detect_truecolor(),
+            command_timeout: None,
+        };
+
+        tmpl.tokens = Arc::new(tmpl.parse_tokens(&Self::normalize(&tmpl.source)));
+        tmpl
+    }
+
+    pub fn from_compiled(compiled: &CompiledTemplate) -> Self {
         Self {
-            template: template.to_string(),
+            source: compiled.source.clone(),
+            tokens: compiled.tokens.clone(),
             state: RefCell::new((ScopedContext::new(), PendingUpdates::new())),
+            styles: RefCell::new(Vec::new()),
+            truecolor: Self::detect_truecolor(),
+            command_timeout: None,
+        }
+    }
+
+    pub fn set_truecolor(&mut self, enabled: bool) { self.truecolor = enabled; }
+
+    // escape codes for a config value like `green`, `#ff8800` or `bold cyan`, None when a word isn't a style
+    pub fn style_escape(&self, styles: &str) -> Option<String> {
+        styles
+            .split_whitespace()
+            .map(|style| match self.parse_static_style(style) {
+                StyleType::Color(name) if name == "reset" && style != "reset" => None,
+                parsed => Some(self.style_code(&parsed)),
+            })
+            .collect()
+    }
+
+    pub fn set_command_timeout(&mut self, timeout: Option<Duration>) { self.command_timeout = timeout; }
+
+    fn detect_truecolor() -> bool { env::var("COLORTERM").is_ok_and(|term| matches!(term.as_str(), "truecolor" | "24bit")) }
+
+    fn nearest_ansi(r: u8, g: u8, b: u8) -> String {
+        let distance = |&(pr, pg, pb): &(u8, u8, u8)| {
+            let (dr, dg, db) = (r as i32 - pr as i32, g as i32 - pg as i32, b as i32 - pb as i32);
+            dr * dr + dg * dg + db * db
+        };
+
+        let index = ANSI_PALETTE.iter().enumerate().min_by_key(|(_, color)| distance(color)).map_or(0, |(i, _)| i);
+        let code = if index < 8 { 30 + index } else { 90 + index - 8 };
+
+        format!("\x1b[{}m", code)
+    }
+
+    pub fn compile(&self) -> CompiledTemplate {
+        CompiledTemplate {
+            source: self.source.clone(),
+            tokens: self.tokens.clone(),
         }
     }
 
@@ -445,24 +587,53 @@ impl<'c> Template<'c> {
         }
     }
 
-    fn execute_command(&self, cmd: &str) -> String {
-        let cmd = cmd.trim_matches('\'').trim_start_matches("cmd(").trim_end_matches(")");
-
-        let parts: Vec<String> = cmd
-            .split('"')
-            .enumerate()
-            .map(|(i, s)| if i % 2 == 0 { s.split_whitespace().map(String::from).collect() } else { vec![s.to_string()] })
-            .flatten()
-            .filter(|s| !s.is_empty())
-            .collect();
+    // None when the command can't start or is killed for running past the timeout
+    fn run_command(&self, cmd: &str) -> Option<(ExitStatus, Vec<u8>)> {
+        let parts: Vec<String> = Tokenizer::new(cmd).collect();
+        let (program, args) = parts.split_first()?;
+
+        let mut child = Command::new(program).args(args).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn().ok()?;
+        let mut stdout = child.stdout.take()?;
+
+        // drain stdout on another thread so a chatty command can't block on a full pipe
+        let reader = thread::spawn(move || {
+            let mut buffer = Vec::new();
+            let _ = stdout.read_to_end(&mut buffer);
+            buffer
+        });
+
+        let deadline = self.command_timeout.map(|timeout| Instant::now() + timeout);
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return None;
+                }
+                Ok(None) => thread::sleep(Duration::from_millis(2)),
+                Err(_) => return None,
+            }
+        };
 
-        if parts.is_empty() {
-            return String::new();
+        Some((status, reader.join().unwrap_or_default()))
+    }
+
+    fn execute_command(&self, cmd: &str) -> String {
+        match self.run_command(cmd) {
+            Some((_, stdout)) => String::from_utf8_lossy(&stdout).trim().to_string(),
+            None => String::new(),
         }
+    }
+
+    // a bare `{if cmd('...')}` tests the exit status, not whether anything was printed
+    fn command_succeeded(&self, cmd: &str) -> bool { self.run_command(cmd).is_some_and(|(status, _)| status.success()) }
 
-        match Command::new(&parts[0]).args(&parts[1..]).output() {
-            Ok(output) => String::from_utf8_lossy(&output.stdout).trim().to_string(),
-            Err(_) => String::new(),
+    // the command inside `cmd('...')`, keeping any quotes or parentheses the command itself ends with
+    fn command_body(content: &str) -> String {
+        match content.trim().strip_prefix("cmd('").and_then(|body| body.strip_suffix("')")) {
+            Some(body) => body.to_string(),
+            None => content[4..].trim_matches('\'').trim_matches(')').to_string(),
         }
     }
 
@@ -478,10 +649,7 @@ impl<'c> Template<'c> {
 
     pub fn render(&self) -> Result<String, Error> {
         let mut state = self.state.borrow_mut();
-
-        let normalized = Self::normalize(&self.template);
-        let tokens = self.parse_tokens(&normalized, &mut state);
-        let result = self.render_tokens_with_context(&tokens, &mut state);
+        let result = self.render_tokens_with_context(&self.tokens, &mut state);
 
         if !state.1.is_empty() {
             let updates = std::mem::replace(&mut state.1, PendingUpdates::new());
@@ -638,28 +806,12 @@ impl<'c> Template<'c> {
                     let style_str = self.render_tokens_with_context(style_tokens, state);
                     let style = self.parse_static_style(&style_str);
 
-                    match &style {
-                        StyleType::Color(name) => result.push_str(ANSI_COLORS.iter().find(|(ansi_name, _)| *ansi_name == name).map_or("", |(_, code)| code)),
-
-                        StyleType::Rgb(r, g, b) => result.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b)),
-
-                        StyleType::Format(format_type) => {
-                            result.push_str(match format_type {
-                                FormatType::Bold => ANSI_BOLD,
-                                FormatType::Italic => ANSI_ITALIC,
-                                FormatType::Underline => ANSI_UNDERLINE,
-                            });
-                        }
-                    }
-
-                    result.push_str(&self.render_tokens_with_context(content, state));
-                    result.push_str(ANSI_RESET);
+                    result.push_str(&self.render_styled(&style, content, state));
                 }
 
-                TemplateToken::Partial { path } => {
-                    if let Ok(content) = std::fs::read_to_string(path) {
-                        let partial_template = Template::new(&content);
-                        let normalized = Self::normalize(&content);
+                TemplateToken::Partial { path, args } => {
+                    if let Some(partial_tokens) = Self::load_partial(path) {
+                        let locals: Vec<(String, String)> = args.iter().map(|(name, value)| (name.clone(), self.evaluate_token_value(value, state))).collect();
 
                         let mut partial_state = (
                             ScopedContext {
@@ -670,7 +822,11 @@ impl<'c> Template<'c> {
                             PendingUpdates::new(),
                         );
 
-                        result.push_str(&partial_template.render_tokens_with_context(&partial_template.parse_tokens(&normalized, &mut partial_state), &mut partial_state));
+                        for (name, value) in locals {
+                            partial_state.0.declare(name, value, false);
+                        }
+
+                        result.push_str(&self.render_tokens_with_context(&partial_tokens, &mut partial_state));
 
                         if !partial_state.1.is_empty() {
                             let updates = std::mem::replace(&mut partial_state.1, PendingUpdates::new());
@@ -706,21 +862,7 @@ impl<'c> Template<'c> {
 
                 TemplateToken::StyleTag { style, content } => {
                     has_formatting = true;
-                    match style {
-                        StyleType::Color(name) => result.push_str(ANSI_COLORS.iter().find(|(ansi_name, _)| *ansi_name == name).map_or("", |(_, code)| code)),
-
-                        StyleType::Rgb(r, g, b) => result.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b)),
-
-                        StyleType::Format(format_type) => {
-                            result.push_str(match format_type {
-                                FormatType::Bold => ANSI_BOLD,
-                                FormatType::Italic => ANSI_ITALIC,
-                                FormatType::Underline => ANSI_UNDERLINE,
-                            });
-                        }
-                    }
-                    result.push_str(&self.render_tokens_with_context(content, state));
-                    result.push_str(ANSI_RESET);
+                    result.push_str(&self.render_styled(style, content, state));
                 }
                 TemplateToken::StringOperation { source, operations } => {
                     let mut op_result = self.evaluate_token_value(source, state);
@@ -746,7 +888,7 @@ impl<'c> Template<'c> {
             }
         }
 
-        if has_formatting && !result.ends_with(ANSI_RESET) {
+        if has_formatting && self.styles.borrow().is_empty() && !result.ends_with(ANSI_RESET) {
             result.push_str(ANSI_RESET);
         }
 
@@ -757,6 +899,35 @@ impl<'c> Template<'c> {
         }
     }
 
+    fn style_code(&self, style: &StyleType) -> String {
+        match style {
+            StyleType::Color(name) => ANSI_COLORS.iter().find(|(ansi_name, _)| *ansi_name == name).map_or("", |(_, code)| code).to_string(),
+            StyleType::Rgb(r, g, b) if self.truecolor => format!("\x1b[38;2;{};{};{}m", r, g, b),
+            StyleType::Rgb(r, g, b) => Self::nearest_ansi(*r, *g, *b),
+            StyleType::Format(format_type) => match format_type {
+                FormatType::Bold => ANSI_BOLD,
+                FormatType::Italic => ANSI_ITALIC,
+                FormatType::Underline => ANSI_UNDERLINE,
+            }
+            .to_string(),
+        }
+    }
+
+    fn render_styled(&self, style: &StyleType, content: &[TemplateToken], state: &mut State) -> String {
+        let code = self.style_code(style);
+        let mut result = code.clone();
+
+        self.styles.borrow_mut().push(code);
+        result.push_str(&self.render_tokens_with_context(content, state));
+        self.styles.borrow_mut().pop();
+
+        // a reset clears every attribute, so re-apply the styles still open around this tag
+        result.push_str(ANSI_RESET);
+        result.push_str(&self.styles.borrow().concat());
+
+        result
+    }
+
     fn render_loop(&self, iterator: &TemplateToken, loop_var: &str, index_var: &Option<String>, body: &[TemplateToken], state: &mut State) -> String {
         let mut result = String::new();
 
@@ -769,14 +940,7 @@ impl<'c> Template<'c> {
                         let items: Vec<String> = array_content.split(',').map(|s| s.trim().trim_matches('\'').trim_matches('"').to_string()).collect();
 
                         for (i, item) in items.iter().enumerate() {
-                            let mut loop_state = (
-                                ScopedContext {
-                                    variables: HashMap::new(),
-                                    constants: HashSet::new(),
-                                    parent: Some(&state.0),
-                                },
-                                PendingUpdates::new(),
-                            );
+                            let mut loop_state = (state.0.child(), PendingUpdates::new());
 
                             loop_state.0.declare(loop_var.to_string(), item.clone(), false);
 
@@ -815,14 +979,7 @@ impl<'c> Template<'c> {
                         }
 
                         for (i, obj) in objects.iter().enumerate() {
-                            let mut loop_state = (
-                                ScopedContext {
-                                    variables: HashMap::new(),
-                                    constants: HashSet::new(),
-                                    parent: Some(&state.0),
-                                },
-                                PendingUpdates::new(),
-                            );
+                            let mut loop_state = (state.0.child(), PendingUpdates::new());
 
                             loop_state.0.declare(loop_var.to_string(), obj.to_string(), false);
 
@@ -839,14 +996,7 @@ impl<'c> Template<'c> {
                 for (i, item) in items.iter().enumerate() {
                     let item_value = self.evaluate_token_value(item, state);
 
-                    let mut loop_state = (
-                        ScopedContext {
-                            variables: HashMap::new(),
-                            constants: HashSet::new(),
-                            parent: Some(&state.0),
-                        },
-                        PendingUpdates::new(),
-                    );
+                    let mut loop_state = (state.0.child(), PendingUpdates::new());
 
                     loop_state.0.declare(loop_var.to_string(), item_value, false);
 
@@ -889,17 +1039,17 @@ impl<'c> Template<'c> {
                     current.push(c);
                 }
                 ',' if !in_quotes && depth == 0 => {
-                    if !current.is_empty() {
-                        items.push(TemplateToken::Text(current.trim_matches(|c| c == '\'' || c == '"').to_string()));
-                        current.clear();
+                    if !current.trim().is_empty() {
+                        items.push(TemplateToken::Text(current.trim().trim_matches(|c| c == '\'' || c == '"').to_string()));
                     }
+                    current.clear();
                 }
                 _ => current.push(c),
             }
         }
 
-        if !current.is_empty() {
-            items.push(TemplateToken::Text(current.trim_matches(|c| c == '\'' || c == '"').to_string()));
+        if !current.trim().is_empty() {
+            items.push(TemplateToken::Text(current.trim().trim_matches(|c| c == '\'' || c == '"').to_string()));
         }
 
         TemplateToken::Array(items)
@@ -908,7 +1058,7 @@ impl<'c> Template<'c> {
     fn apply_operation(&self, input: &str, op: &Operation) -> String {
         match op.operation_type {
             StringOperationType::DefaultValue => {
-                if input.is_empty() {
+                if input.trim().is_empty() {
                     op.pattern.as_ref().map_or(String::new(), |default| default.clone())
                 } else {
                     input.to_string()
@@ -922,6 +1072,13 @@ impl<'c> Template<'c> {
                     input.to_string()
                 }
             }
+            StringOperationType::RegexReplace => match (&op.pattern, &op.param) {
+                (Some(pattern), Some(OperationParam::ReplaceStr(replacement))) => match Regex::new(pattern) {
+                    Ok(re) => re.replace_all(input, replacement.as_str()).into_owned(),
+                    Err(_) => input.to_string(),
+                },
+                _ => input.to_string(),
+            },
             StringOperationType::Split => {
                 if let (Some(delimiter), Some(OperationParam::Index(index))) = (&op.pattern, &op.param) {
                     let parts: Vec<&str> = input.split(delimiter).collect();
@@ -934,14 +1091,82 @@ impl<'c> Template<'c> {
                     input.to_string()
                 }
             }
+            StringOperationType::Length => Self::value_length(input).to_string(),
+            StringOperationType::Substr => {
+                let chars: Vec<char> = input.chars().collect();
+                let start = op.pattern.as_ref().and_then(|s| s.trim().parse::<i64>().ok()).unwrap_or(0);
+
+                let start = if start < 0 {
+                    chars.len().saturating_sub(start.unsigned_abs() as usize)
+                } else {
+                    (start as usize).min(chars.len())
+                };
+
+                let end = match op.param {
+                    Some(OperationParam::Index(len)) => start.saturating_add(len).min(chars.len()),
+                    _ => chars.len(),
+                };
+
+                chars[start..end].iter().collect()
+            }
+            StringOperationType::Abbreviate => {
+                let keep = op.pattern.as_ref().and_then(|n| n.trim().parse::<usize>().ok()).unwrap_or(1);
+                let segments: Vec<&str> = input.split('/').collect();
+                let abbreviated = segments.len().saturating_sub(keep);
+
+                segments
+                    .iter()
+                    .enumerate()
+                    .map(|(i, segment)| {
+                        let prefix_len = if segment.starts_with('.') { 2 } else { 1 };
+                        match i < abbreviated && *segment != "~" {
+                            true => segment.chars().take(prefix_len).collect(),
+                            false => segment.to_string(),
+                        }
+                    })
+                    .collect::<Vec<String>>()
+                    .join("/")
+            }
+            StringOperationType::Truncate => {
+                let max_len = op.pattern.as_ref().and_then(|n| n.trim().parse::<usize>().ok()).unwrap_or(usize::MAX);
+                if input.chars().count() <= max_len {
+                    return input.to_string();
+                }
+
+                let ellipsis = match &op.param {
+                    Some(OperationParam::ReplaceStr(ellipsis)) => ellipsis.as_str(),
+                    _ => "…",
+                };
+
+                format!("{}{ellipsis}", input.chars().take(max_len).collect::<String>())
+            }
+            StringOperationType::PadLeft | StringOperationType::PadRight => {
+                let width = op.pattern.as_ref().and_then(|n| n.trim().parse::<usize>().ok()).unwrap_or(0);
+                let fill = match &op.param {
+                    Some(OperationParam::ReplaceStr(fill)) => fill.chars().next().unwrap_or(' '),
+                    _ => ' ',
+                };
+
+                let padding = fill.to_string().repeat(width.saturating_sub(Self::visible_width(input)));
+                match op.operation_type {
+                    StringOperationType::PadLeft => format!("{padding}{input}"),
+                    _ => format!("{input}{padding}"),
+                }
+            }
             StringOperationType::Match => {
                 if let Some(pattern) = &op.pattern {
                     if let Ok(re) = Regex::new(pattern) {
                         if let Some(captures) = re.captures(input) {
-                            if let Some(OperationParam::Index(group_idx)) = op.param {
-                                if group_idx > 0 && group_idx <= captures.len() {
-                                    return captures.get(group_idx).map(|m| m.as_str().to_string()).unwrap_or_default();
+                            match &op.param {
+                                Some(OperationParam::Index(group_idx)) if *group_idx > 0 && *group_idx <= captures.len() => {
+                                    return captures.get(*group_idx).map(|m| m.as_str().to_string()).unwrap_or_default();
+                                }
+                                Some(OperationParam::Name(group_name)) => {
+                                    if let Some(group) = captures.name(group_name) {
+                                        return group.as_str().to_string();
+                                    }
                                 }
+                                _ => {}
                             }
                             return captures.get(0).map(|m| m.as_str().to_string()).unwrap_or_default();
                         }
@@ -952,13 +1177,50 @@ impl<'c> Template<'c> {
         }
     }
 
-    fn parse_tokens(&self, template: &str, state: &mut State) -> Vec<TemplateToken> {
+    fn visible_width(input: &str) -> usize {
+        let mut width = 0;
+        let mut chars = input.chars();
+
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                // skip the escape sequence up to its final byte
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            } else {
+                width += 1;
+            }
+        }
+
+        width
+    }
+
+    fn value_length(input: &str) -> usize {
+        let trimmed = input.trim();
+        if !(trimmed.starts_with('[') && trimmed.ends_with(']')) {
+            return input.chars().count();
+        }
+
+        if let Ok(serde_json::Value::Array(items)) = serde_json::from_str(&trimmed.replace('\'', "\"")) {
+            return items.len();
+        }
+
+        let content = &trimmed[1..trimmed.len() - 1];
+        content.split(',').map(str::trim).filter(|item| !item.is_empty()).count()
+    }
+
+    fn parse_tokens(&self, template: &str) -> Vec<TemplateToken> {
         let mut tokens = Vec::new();
         let mut chars = template.chars().peekable();
         let mut current_text = String::new();
 
         while let Some(c) = chars.next() {
             match c {
+                '\\' if matches!(chars.peek(), Some('{' | '}' | '<')) => {
+                    current_text.extend(chars.next());
+                }
                 '<' => {
                     if chars.peek().map_or(false, |&next| next == 's') && {
                         chars.next();
@@ -968,7 +1230,7 @@ impl<'c> Template<'c> {
                             tokens.push(TemplateToken::Text(current_text.clone()));
                             current_text.clear();
                         }
-                        tokens.push(self.parse_style_tag(&mut chars, state));
+                        tokens.push(self.parse_style_tag(&mut chars));
                     } else {
                         current_text.push('<');
                     }
@@ -978,7 +1240,7 @@ impl<'c> Template<'c> {
                         tokens.push(TemplateToken::Text(current_text.clone()));
                         current_text.clear();
                     }
-                    tokens.push(self.parse_special_token(&mut chars, state));
+                    tokens.push(self.parse_special_token(&mut chars));
                 }
                 _ => current_text.push(c),
             }
@@ -991,13 +1253,14 @@ impl<'c> Template<'c> {
         tokens
     }
 
-    fn parse_style_tag(&self, chars: &mut Peekable<Chars>, state: &mut State) -> TemplateToken {
+    fn parse_style_tag(&self, chars: &mut Peekable<Chars>) -> TemplateToken {
         chars.next(); // Skip '.'
 
         let mut style_expr = String::new();
         let mut content = Vec::new();
         let mut nested = String::new();
         let mut brace_depth = 0;
+        let mut tag_depth = 0;
         let mut parser_state = StyleParserState::CollectingStyle;
 
         while let Some(c) = chars.next() {
@@ -1013,8 +1276,15 @@ impl<'c> Template<'c> {
                 ('>', StyleParserState::CollectingStyle) if brace_depth == 0 => {
                     parser_state = StyleParserState::WaitingForContent;
                 }
+                ('\\', StyleParserState::CollectingContent) if chars.peek() == Some(&'<') => {
+                    nested.push(c);
+                    nested.extend(chars.next());
+                }
                 ('<', StyleParserState::CollectingContent) => {
-                    if chars.peek() == Some(&'/') {
+                    if chars.peek() == Some(&'/') && tag_depth > 0 {
+                        tag_depth -= 1;
+                        nested.push(c);
+                    } else if chars.peek() == Some(&'/') {
                         chars.next(); // skip '/'
                         chars.next(); // skip 's'
                         while let Some(c) = chars.next() {
@@ -1038,16 +1308,19 @@ impl<'c> Template<'c> {
                 }
                 (c, StyleParserState::CollectingContent) => {
                     nested.push(c);
+                    if nested.ends_with("<s.") && !nested.ends_with("\\<s.") {
+                        tag_depth += 1;
+                    }
                 }
             }
         }
 
         if !nested.is_empty() {
-            content = self.parse_tokens(&nested, state);
+            content = self.parse_tokens(&nested);
         }
 
         if style_expr.starts_with('{') && style_expr.ends_with('}') {
-            let style_tokens = self.parse_tokens(&style_expr, state);
+            let style_tokens = self.parse_tokens(&style_expr);
             return TemplateToken::DynamicStyleTag { style_tokens, content };
         }
 
@@ -1055,17 +1328,37 @@ impl<'c> Template<'c> {
         TemplateToken::StyleTag { style, content }
     }
 
-    fn parse_special_token(&self, chars: &mut std::iter::Peekable<std::str::Chars>, state: &mut State) -> TemplateToken {
+    // odd depths are expressions and even depths are bodies, `{if x == '{' {it's}}`, so only
+    // the former track quotes and an apostrophe in body text stays text
+    fn is_expression_depth(depth: i32) -> bool { depth % 2 != 0 }
+
+    fn parse_special_token(&self, chars: &mut Peekable<Chars>) -> TemplateToken {
         let mut content = String::new();
         let mut depth = 1;
+        let mut quote_char = None;
+        let mut in_command = false;
 
         while let Some(c) = chars.next() {
             match c {
-                '{' => {
+                '\\' if matches!(chars.peek(), Some('{' | '}')) => {
+                    content.push(c);
+                    content.extend(chars.next());
+                }
+                '\'' | '"' if quote_char.is_none() && Self::is_expression_depth(depth) => {
+                    // a `cmd('...')` body only ends at `')`, so the command can use its own quotes
+                    in_command = c == '\'' && content.ends_with("cmd(");
+                    quote_char = Some(c);
+                    content.push(c);
+                }
+                c if Some(c) == quote_char && (!in_command || chars.peek() == Some(&')')) => {
+                    quote_char = None;
+                    content.push(c);
+                }
+                '{' if quote_char.is_none() => {
                     depth += 1;
                     content.push(c);
                 }
-                '}' => {
+                '}' if quote_char.is_none() => {
                     depth -= 1;
                     if depth == 0 {
                         break;
@@ -1096,14 +1389,23 @@ impl<'c> Template<'c> {
                 };
             }
 
-            let var_name = left;
+            let (var_name, is_env) = match left.strip_prefix('$') {
+                Some(env_name) => (env_name, true),
+                None => (left, false),
+            };
             let default_value = right;
 
-            if !var_name.contains(|c: char| !c.is_alphanumeric() && c != '_')
+            if !var_name.is_empty()
+                && !var_name.contains(|c: char| !c.is_alphanumeric() && c != '_')
                 && ((default_value.starts_with('\'') && default_value.ends_with('\'')) || (default_value.starts_with('"') && default_value.ends_with('"')))
             {
+                let source = match is_env {
+                    true => TemplateToken::EnvironmentVariable(var_name.to_string()),
+                    false => TemplateToken::Variable(var_name.to_string()),
+                };
+
                 return TemplateToken::StringOperation {
-                    source: Box::new(TemplateToken::Variable(var_name.to_string())),
+                    source: Box::new(source),
                     operations: vec![Operation {
                         operation_type: StringOperationType::DefaultValue,
                         pattern: Some(Self::strip_quotes(default_value).to_string()),
@@ -1129,33 +1431,24 @@ impl<'c> Template<'c> {
                     let iterator = if iterator_expr.starts_with('[') {
                         Box::new(self.parse_array(iterator_expr))
                     } else if iterator_expr.contains("..") {
-                        let range: Vec<&str> = iterator_expr.split("..").collect();
-                        if range.len() == 2 {
-                            if let (Ok(start), Ok(end)) = (range[0].trim().parse::<i64>(), range[1].trim().parse::<i64>()) {
-                                let numbers: Vec<TemplateToken> = (start..end).map(|n| TemplateToken::Text(n.to_string())).collect();
-                                Box::new(TemplateToken::Array(numbers))
-                            } else {
-                                Box::new(TemplateToken::Text(iterator_expr.to_string()))
-                            }
-                        } else {
-                            Box::new(self.parse_value_token(iterator_expr))
+                        match Self::parse_range(iterator_expr) {
+                            Some(numbers) => Box::new(TemplateToken::Array(numbers.into_iter().map(TemplateToken::Text).collect())),
+                            None => Box::new(TemplateToken::Text(iterator_expr.to_string())),
                         }
                     } else {
                         Box::new(self.parse_value_token(iterator_expr))
                     };
 
                     let body_content = self.extract_block(&loop_content[in_pos + brace_pos..]);
-                    let body = self.parse_tokens(&body_content, state);
+                    let body = self.parse_tokens(&body_content);
 
                     return TemplateToken::Loop { iterator, loop_var, index_var, body };
                 }
             }
         }
 
-        if trimmed.starts_with('>') {
-            return TemplateToken::Partial {
-                path: trimmed[1..].trim().to_string(),
-            };
+        if let Some(partial) = trimmed.strip_prefix('>') {
+            return self.parse_partial(partial.trim());
         }
 
         if trimmed.starts_with('[') && trimmed.ends_with(']') {
@@ -1167,10 +1460,10 @@ impl<'c> Template<'c> {
         }
 
         if trimmed.starts_with("if ") {
-            return self.parse_conditional(&trimmed[3..], state);
+            return self.parse_conditional(&trimmed[3..]);
         }
 
-        if !trimmed.starts_with("let") && !trimmed.starts_with("const") && trimmed.contains('=') {
+        if !trimmed.starts_with("let") && !trimmed.starts_with("const") && !trimmed.starts_with("cmd('") && trimmed.contains('=') {
             return self.parse_variable_assignment(&content);
         }
 
@@ -1202,14 +1495,116 @@ impl<'c> Template<'c> {
                 TemplateToken::Text(content.to_string())
             }
         } else if content.starts_with("cmd('") {
-            TemplateToken::Command(content[4..].trim_matches('\'').trim_matches(')').to_string())
-        } else if content.starts_with("match(") || content.starts_with("split(") || content.starts_with("replace(") {
+            TemplateToken::Command(Self::command_body(&content))
+        } else if Self::operation_call(&content).is_some() {
             self.parse_single_operation(&content)
         } else {
             TemplateToken::Variable(content.trim().to_string())
         }
     }
 
+    fn parse_range(expr: &str) -> Option<Vec<String>> {
+        let range: Vec<&str> = expr.split("..").map(str::trim).collect();
+        if range.len() != 2 && range.len() != 3 {
+            return None;
+        }
+
+        let (end, inclusive) = match range[1].strip_prefix('=') {
+            Some(end) => (end.trim(), true),
+            None => (range[1], false),
+        };
+
+        let bounds = [range[0], end, range.get(2).copied().unwrap_or("1")];
+        let integers = bounds.iter().all(|n| n.parse::<i64>().is_ok());
+        let [start, end, step] = bounds.map(|n| n.parse::<f64>().ok());
+        let (start, end, step) = (start?, end?, step?);
+
+        if step == 0.0 || !start.is_finite() || !end.is_finite() {
+            return None;
+        }
+
+        let step = if start > end { -step.abs() } else { step.abs() };
+        if (end - start) / step + if inclusive { 1.0 } else { 0.0 } > MAX_RANGE_LEN {
+            return None;
+        }
+
+        let epsilon = step.abs() * 1e-9;
+        let mut numbers = Vec::new();
+
+        for i in 0.. {
+            let n = start + step * i as f64;
+            let in_range = match (step > 0.0, inclusive) {
+                (true, true) => n <= end + epsilon,
+                (true, false) => n < end - epsilon,
+                (false, true) => n >= end - epsilon,
+                (false, false) => n > end + epsilon,
+            };
+
+            if !in_range {
+                break;
+            }
+
+            numbers.push(if integers { (n as i64).to_string() } else { n.to_string() });
+        }
+
+        Some(numbers)
+    }
+
+    fn load_partial(path: &str) -> Option<Arc<Vec<TemplateToken>>> {
+        let path = PathBuf::from(EnvManager::expand_tilde(path));
+
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        if let Some((cached_at, tokens)) = PARTIAL_CACHE.lock().expect("Able to lock partial cache").get(&path) {
+            if *cached_at == modified {
+                return Some(tokens.clone());
+            }
+        }
+
+        // read and parsed without holding the lock, so other renders only wait on the lookup
+        let content = std::fs::read_to_string(&path).ok()?;
+        let tokens = Template::new(&content).tokens;
+
+        PARTIAL_CACHE.lock().expect("Able to lock partial cache").insert(path, (modified, tokens.clone()));
+        Some(tokens)
+    }
+
+    fn parse_partial(&self, content: &str) -> TemplateToken {
+        let mut words = Vec::new();
+        let mut current = String::new();
+        let mut quote_char = None;
+
+        for c in content.chars() {
+            match c {
+                '\'' | '"' if quote_char.is_none() => {
+                    quote_char = Some(c);
+                    current.push(c);
+                }
+                c if Some(c) == quote_char => {
+                    quote_char = None;
+                    current.push(c);
+                }
+                c if c.is_whitespace() && quote_char.is_none() => {
+                    if !current.is_empty() {
+                        words.push(std::mem::take(&mut current));
+                    }
+                }
+                _ => current.push(c),
+            }
+        }
+
+        if !current.is_empty() {
+            words.push(current);
+        }
+
+        let mut words = words.into_iter();
+        let path = words.next().unwrap_or_default();
+        let args = words
+            .filter_map(|word| word.split_once('=').map(|(name, value)| (name.trim().to_string(), self.parse_value_token(value.trim()))))
+            .collect();
+
+        TemplateToken::Partial { path, args }
+    }
+
     fn parse_variable_assignment(&self, content: &str) -> TemplateToken {
         let parts: Vec<&str> = content.split('=').map(|s| s.trim()).collect();
 
@@ -1219,7 +1614,7 @@ impl<'c> Template<'c> {
 
         let name = parts[0].to_string();
         let value = if parts[1].trim().starts_with("if ") {
-            Box::new(self.parse_conditional(parts[1], &mut (ScopedContext::new(), PendingUpdates::new())))
+            Box::new(self.parse_conditional(parts[1]))
         } else {
             Box::new(self.parse_value_token(parts[1]))
         };
@@ -1245,7 +1640,7 @@ impl<'c> Template<'c> {
         }
 
         let value = if value_str.starts_with("if ") {
-            Box::new(self.parse_conditional(value_str, &mut (ScopedContext::new(), PendingUpdates::new())))
+            Box::new(self.parse_conditional(value_str))
         } else if value_str.contains('|') {
             Box::new(self.parse_chained_operations(value_str))
         } else {
@@ -1257,7 +1652,7 @@ impl<'c> Template<'c> {
 
     fn parse_value_token(&self, value: &str) -> TemplateToken {
         if value.starts_with("cmd('") {
-            TemplateToken::Command(value[4..].trim_matches('\'').trim_matches(')').to_string())
+            TemplateToken::Command(Self::command_body(value))
         } else if value.starts_with('\'') && value.ends_with('\'') {
             TemplateToken::Text(value[1..value.len() - 1].to_string())
         } else if value.starts_with('$') {
@@ -1272,7 +1667,7 @@ impl<'c> Template<'c> {
     }
 
     fn parse_chained_operations(&self, content: &str) -> TemplateToken {
-        let parts: Vec<&str> = content.split('|').map(str::trim).collect();
+        let parts: Vec<&str> = Self::split_unquoted(content, '|').into_iter().map(str::trim).collect();
         if parts.is_empty() {
             return TemplateToken::Text(content.to_string());
         }
@@ -1311,15 +1706,18 @@ impl<'c> Template<'c> {
         TemplateToken::StringOperation { source: Box::new(source), operations }
     }
 
+    fn operation_call(op_str: &str) -> Option<(StringOperationType, &str)> {
+        STRING_OPERATIONS.iter().find_map(|(name, op_type)| {
+            let args = op_str.strip_prefix(name)?.strip_prefix('(')?;
+            Some((op_type.clone(), args.strip_suffix(')').unwrap_or(args)))
+        })
+    }
+
     fn parse_operation(&self, op_str: &str) -> Option<Operation> {
-        let (op_type, args) = if op_str.starts_with("match(") {
-            (StringOperationType::Match, &op_str[6..op_str.len() - 1])
-        } else if op_str.starts_with("split(") {
-            (StringOperationType::Split, &op_str[6..op_str.len() - 1])
-        } else if op_str.starts_with("replace(") {
-            (StringOperationType::Replace, &op_str[8..op_str.len() - 1])
-        } else {
-            return None;
+        let (op_type, args) = match op_str {
+            "abbreviate" => (StringOperationType::Abbreviate, ""),
+            "length" | "length()" => (StringOperationType::Length, ""),
+            _ => Self::operation_call(op_str)?,
         };
 
         let mut parts = Vec::new();
@@ -1345,10 +1743,21 @@ impl<'c> Template<'c> {
             parts.push(current.trim().to_string());
         }
 
-        let pattern = parts.get(0).map(|p| p.trim_matches('\'').trim_matches('"').to_string());
+        let mut pattern = parts.get(0).map(|p| p.trim_matches('\'').trim_matches('"').to_string());
+
+        // flags always follow the group, as in match('foo', 0, 'i'), so they can't be mistaken for a group name
+        if let StringOperationType::Match = op_type {
+            if let Some(flags) = parts.get(2).and_then(|f| Self::regex_flags(f.trim_matches('\'').trim_matches('"'))) {
+                pattern = pattern.map(|p| format!("(?{flags}){p}"));
+            }
+        }
 
         let param = match op_type {
-            StringOperationType::Replace => parts.get(1).map(|r| OperationParam::ReplaceStr(r.trim_matches('\'').trim_matches('"').to_string())),
+            StringOperationType::Replace | StringOperationType::RegexReplace | StringOperationType::Truncate | StringOperationType::PadLeft | StringOperationType::PadRight => parts.get(1).map(|r| OperationParam::ReplaceStr(r.trim_matches('\'').trim_matches('"').to_string())),
+            StringOperationType::Match => parts.get(1).map(|g| match g.trim().parse() {
+                Ok(index) => OperationParam::Index(index),
+                Err(_) => OperationParam::Name(g.trim_matches('\'').trim_matches('"').to_string()),
+            }),
             _ => parts.get(1).and_then(|g| g.trim().parse().ok()).map(OperationParam::Index),
         };
 
@@ -1380,6 +1789,17 @@ impl<'c> Template<'c> {
         }
     }
 
+    // items match numerically when both sides are numbers, so `5` is in `05, 10` and `5.0`
+    fn list_contains(list: &str, item: &str) -> bool {
+        let item = item.trim();
+        let item_num = item.parse::<f64>().ok();
+
+        list.split(',').map(str::trim).any(|x| match (item_num, x.parse::<f64>()) {
+            (Some(a), Ok(b)) => a == b,
+            _ => x == item,
+        })
+    }
+
     fn apply_operator(&self, lhs: &str, op: Operator, rhs: &str) -> bool {
         use Operator::*;
         match op {
@@ -1393,8 +1813,15 @@ impl<'c> Template<'c> {
             StartsWith => lhs.starts_with(rhs),
             EndsWith => lhs.ends_with(rhs),
             Matches => Regex::new(rhs).map(|re| re.is_match(lhs)).unwrap_or(false),
-            In => rhs.split(',').map(str::trim).any(|x| x == lhs),
-            NotIn => !rhs.split(',').map(str::trim).any(|x| x == lhs),
+            MatchesIgnoreCase => Regex::new(&format!("(?i){rhs}")).map(|re| re.is_match(lhs)).unwrap_or(false),
+            MatchesAny => rhs
+                .split([',', '|'])
+                .map(str::trim)
+                .filter(|pattern| !pattern.is_empty())
+                .filter_map(|pattern| Regex::new(pattern).ok())
+                .any(|re| re.is_match(lhs)),
+            In => Self::list_contains(rhs, lhs),
+            NotIn => !Self::list_contains(rhs, lhs),
             LengthEquals => lhs.len() == rhs.parse().unwrap_or(0),
             LengthGreater => lhs.len() > rhs.parse().unwrap_or(0),
             LengthLess => lhs.len() < rhs.parse().unwrap_or(0),
@@ -1425,6 +1852,15 @@ impl<'c> Template<'c> {
                 }
                 a <= b
             }),
+            Between => match rhs.split_once("..") {
+                Some((low, high)) => {
+                    let high = high.trim_start_matches('=');
+                    let numeric = |a: &str, b: &str, f: fn(f64, f64) -> bool| matches!((a.parse::<f64>(), b.parse::<f64>()), (Ok(a), Ok(b)) if f(a, b));
+
+                    self.compare_values(lhs, low, |a, b| numeric(a, b, |a, b| a >= b)) && self.compare_values(lhs, high, |a, b| numeric(a, b, |a, b| a <= b))
+                }
+                None => false,
+            },
         }
     }
 
@@ -1487,8 +1923,12 @@ impl<'c> Template<'c> {
                     }
                 }
             }
+            ConditionType::Command(cmd) => self.command_succeeded(cmd),
+
             ConditionType::Boolean(inner, negate) => {
-                let result = if let Some(val) = self.resolve_value(inner, context) {
+                let result = if let ConditionType::Command(cmd) = inner.as_ref() {
+                    self.command_succeeded(cmd)
+                } else if let Some(val) = self.resolve_value(inner, context) {
                     Self::is_truthy(&val)
                 } else {
                     self.evaluate_condition_internal(inner, context)
@@ -1545,37 +1985,31 @@ impl<'c> Template<'c> {
         compare_fn(&v1_normalized, &v2_normalized)
     }
 
-    fn parse_conditional(&self, content: &str, state: &mut State) -> TemplateToken {
+    fn parse_conditional(&self, content: &str) -> TemplateToken {
         let mut content_remaining = content;
         let mut if_chain = Vec::new();
 
-        loop {
-            if let Some(condition_end) = self.find_next_condition_end(content_remaining) {
-                let condition_str = &content_remaining[..condition_end];
-                let condition = self.parse_condition_expression(condition_str);
+        while let Some(condition_end) = self.find_next_condition_end(content_remaining) {
+            let condition_str = &content_remaining[..condition_end];
+            let condition = self.parse_condition_expression(condition_str);
 
-                let (body_content, remaining) = self.extract_next_body(&content_remaining[condition_end..]);
-                let body = self.parse_tokens(&body_content, state);
+            let (body_content, remaining) = self.extract_next_body(&content_remaining[condition_end..]);
+            let body = self.parse_tokens(&body_content);
 
-                if_chain.push((condition, body));
-                content_remaining = remaining.trim();
+            if_chain.push((condition, body));
+            content_remaining = remaining.trim();
 
-                if let Some(else_if_pos) = content_remaining.find("else if") {
-                    content_remaining = &content_remaining[else_if_pos + 7..];
-                    continue;
-                }
-                break;
+            if let Some(rest) = content_remaining.strip_prefix("else if ").or_else(|| content_remaining.strip_prefix("elif ")) {
+                content_remaining = rest;
+                continue;
             }
             break;
         }
 
-        let else_body = if let Some(else_pos) = content_remaining.find("else") {
-            let else_content = &content_remaining[else_pos + 4..];
+        let else_body = content_remaining.strip_prefix("else").map(|else_content| {
             let else_block = self.extract_block(else_content);
-            Some(self.parse_tokens(&else_block, state))
-        } else {
-            None
-        };
+            self.parse_tokens(&else_block)
+        });
 
         if if_chain.is_empty() {
             return TemplateToken::Text(String::new());
@@ -1606,66 +2040,52 @@ impl<'c> Template<'c> {
 
     fn find_next_condition_end(&self, content: &str) -> Option<usize> {
         let mut depth = 0;
-        let mut in_quotes = false;
-        let mut in_parens = false;
+        let mut quote_char = None;
+        let mut prev = None;
 
-        for (i, c) in content.chars().enumerate() {
+        for (i, c) in content.char_indices() {
+            let escaped = prev.replace(c) == Some('\\');
             match c {
-                '\'' | '"' => in_quotes = !in_quotes,
-                '(' if !in_quotes => {
-                    in_parens = true;
-                    depth += 1;
-                }
-                ')' if !in_quotes => {
-                    depth -= 1;
-                    if depth == 0 {
-                        in_parens = false;
-                    }
-                }
-                '{' if !in_quotes && !in_parens => {
-                    return Some(i);
-                }
+                '\'' | '"' if quote_char.is_none() => quote_char = Some(c),
+                c if Some(c) == quote_char => quote_char = None,
+                '(' if quote_char.is_none() => depth += 1,
+                ')' if quote_char.is_none() => depth -= 1,
+                '{' if quote_char.is_none() && !escaped && depth == 0 => return Some(i),
                 _ => {}
             }
         }
         None
     }
 
+    // starts before the body, so the body itself is at depth 1 and nested expressions at even depths
     fn extract_next_body<'a>(&self, content: &'a str) -> (String, &'a str) {
         let mut depth = 0;
-        let mut in_quotes = false;
-        let mut body_start = 0;
-        let mut body_end = 0;
-        let mut started = false;
+        let mut quote_char = None;
+        let mut body_start = None;
+        let mut prev = None;
 
-        for (i, c) in content.chars().enumerate() {
+        for (i, c) in content.char_indices() {
+            let escaped = prev.replace(c) == Some('\\');
             match c {
-                '\'' | '"' => in_quotes = !in_quotes,
-                '{' if !in_quotes => {
+                '\'' | '"' if quote_char.is_none() && !Self::is_expression_depth(depth) => quote_char = Some(c),
+                c if Some(c) == quote_char => quote_char = None,
+                '{' if quote_char.is_none() && !escaped => {
                     depth += 1;
                     if depth == 1 {
-                        body_start = i + 1;
-                        started = true;
+                        body_start = Some(i + 1);
                     }
                 }
-                '}' if !in_quotes => {
+                '}' if quote_char.is_none() && !escaped => {
                     depth -= 1;
-                    if depth == 0 && started {
-                        body_end = i;
-                        break;
+                    if let (0, Some(start)) = (depth, body_start) {
+                        return (content[start..i].trim().to_string(), &content[i + 1..]);
                     }
                 }
                 _ => {}
             }
         }
 
-        if body_end > body_start {
-            let body = content[body_start..body_end].trim().to_string();
-            let remaining = if body_end + 1 < content.len() { &content[body_end + 1..] } else { "" };
-            (body, remaining)
-        } else {
-            (String::new(), content)
-        }
+        (String::new(), content)
     }
 
     fn parse_condition_expression(&self, expr: &str) -> ConditionType {
@@ -1707,8 +2127,8 @@ impl<'c> Template<'c> {
             return ConditionType::Boolean(Box::new(inner), true);
         }
 
-        if clean_expr.contains('|') {
-            let parts: Vec<&str> = clean_expr.split('|').map(str::trim).collect();
+        if Self::contains_unquoted(clean_expr, '|') {
+            let parts: Vec<&str> = Self::split_unquoted(clean_expr, '|').into_iter().map(str::trim).collect();
             let source = parts[0].trim();
             let mut operations = Vec::new();
 
@@ -1813,7 +2233,7 @@ impl<'c> Template<'c> {
             }
         }
 
-        if clean_expr.parse::<f64>().is_ok() {
+        if clean_expr.parse::<f64>().is_ok() || Self::is_numeric_range(clean_expr) {
             return ConditionType::Literal(clean_expr.to_string());
         }
 
@@ -1823,6 +2243,51 @@ impl<'c> Template<'c> {
         }
     }
 
+    // None for anything that isn't a valid set of inline regex flags, so bad flags are ignored
+    fn regex_flags(flags: &str) -> Option<&str> {
+        (!flags.is_empty() && flags.chars().all(|c| "imsxU".contains(c))).then_some(flags)
+    }
+
+    fn is_numeric_range(s: &str) -> bool {
+        s.split_once("..")
+            .is_some_and(|(low, high)| low.parse::<f64>().is_ok() && high.trim_start_matches('=').parse::<f64>().is_ok())
+    }
+
+    fn contains_unquoted(s: &str, needle: char) -> bool {
+        let mut quote_char = None;
+
+        for c in s.chars() {
+            match (c, quote_char) {
+                ('\'' | '"', None) => quote_char = Some(c),
+                (c, Some(q)) if c == q => quote_char = None,
+                (c, None) if c == needle => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn split_unquoted(s: &str, needle: char) -> Vec<&str> {
+        let mut parts = Vec::new();
+        let mut quote_char = None;
+        let mut start = 0;
+
+        for (i, c) in s.char_indices() {
+            match (c, quote_char) {
+                ('\'' | '"', None) => quote_char = Some(c),
+                (c, Some(q)) if c == q => quote_char = None,
+                (c, None) if c == needle => {
+                    parts.push(&s[start..i]);
+                    start = i + c.len_utf8();
+                }
+                _ => {}
+            }
+        }
+
+        parts.push(&s[start..]);
+        parts
+    }
+
     fn strip_quotes(s: &str) -> &str {
         if (s.starts_with('\'') && s.ends_with('\'')) || (s.starts_with('"') && s.ends_with('"')) {
             if s.len() >= 2 {
@@ -1849,10 +2314,12 @@ impl<'c> Template<'c> {
         let mut quote_char = None;
         let mut start_pos = None;
         let mut end_pos = None;
+        let mut prev = None;
 
-        for (i, c) in content.chars().enumerate() {
+        for (i, c) in content.char_indices() {
+            let escaped = prev.replace(c) == Some('\\');
             match c {
-                '\'' | '"' if !in_quotes => {
+                '\'' | '"' if !in_quotes && !Self::is_expression_depth(depth) => {
                     in_quotes = true;
                     quote_char = Some(c);
                 }
@@ -1860,13 +2327,13 @@ impl<'c> Template<'c> {
                     in_quotes = false;
                     quote_char = None;
                 }
-                '{' if !in_quotes => {
+                '{' if !in_quotes && !escaped => {
                     if depth == 0 {
                         start_pos = Some(i + 1);
                     }
                     depth += 1;
                 }
-                '}' if !in_quotes => {
+                '}' if !in_quotes && !escaped => {
                     depth -= 1;
                     if depth == 0 {
                         end_pos = Some(i);
@@ -1884,3 +2351,132 @@ impl<'c> Template<'c> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str) -> String { Template::new(source).render().expect("template renders") }
+
+    #[test]
+    fn conditional_quoted_braces() {
+        assert_eq!(render("{let c = '{'}{if c == '{' {yes} else {no}}"), "yes");
+        assert_eq!(render("{let c = '}'}{if c == '{' {yes} elif c == '}' {close} else {no}}"), "close");
+    }
+
+    #[test]
+    fn conditional_empty_body_keeps_else() {
+        assert_eq!(render("{if 1 == 2 {} else {fallback}}"), "fallback");
+        assert_eq!(render("{if 1 == 1 {} else {fallback}}x"), "x");
+    }
+
+    #[test]
+    fn conditional_apostrophes_in_bodies() {
+        assert_eq!(render("{let s = 'a'}{if s equals 'a' {it's ok}} X>"), "it's ok X>");
+        assert_eq!(render("{if 1 == 2 {nope} else {it's else}}"), "it's else");
+        assert_eq!(render("{if 1 == 1 {don't {if 2 == 2 {stop}}}}"), "don't stop");
+    }
+
+    #[test]
+    fn style_tags_nest_two_levels() {
+        assert_eq!(render("<s.red>a <s.bold>b</s> c</s>"), "\x1b[31ma \x1b[1mb\x1b[0m\x1b[31m c\x1b[0m");
+    }
+
+    #[test]
+    fn style_tags_nest_three_levels() {
+        assert_eq!(
+            render("<s.red>a <s.bold>b <s.u>c</s> d</s> e</s>"),
+            "\x1b[31ma \x1b[1mb \x1b[4mc\x1b[0m\x1b[31m\x1b[1m d\x1b[0m\x1b[31m e\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn nested_loops_see_outer_variable() {
+        assert_eq!(render("{for x in [1, 2] {{for y in ['a', 'b'] {{x}{y} }}}}"), "1a1b2a2b");
+    }
+
+    #[test]
+    fn nested_loops_over_variables() {
+        assert_eq!(render("{let outer = [1, 2]}{let inner = [3]}{for x in outer {{for y in inner {{x}{y},}}}}"), "13,23,");
+    }
+
+    #[test]
+    fn escaped_braces_render_literally() {
+        assert_eq!(render(r"\{literal\}"), "{literal}");
+        assert_eq!(render(r#"\{"user": "{let u = 'me'}{u}"\}"#), r#"{"user": "me"}"#);
+    }
+
+    #[test]
+    fn escaped_braces_inside_bodies() {
+        assert_eq!(render(r"{if 1 == 1 {\{json\}}}"), "{json}");
+    }
+
+    #[test]
+    fn escaped_angle_bracket_is_not_a_style() {
+        assert_eq!(render(r"\<s.red>x"), "<s.red>x");
+    }
+
+    #[test]
+    fn in_matches_numbers_by_value() {
+        assert!(Template::list_contains("05", "5"));
+        assert!(Template::list_contains("1, 5.0", "05"));
+        assert!(!Template::list_contains("50, 15", "5"));
+        assert_eq!(render("{if 5 in '05' {yes} else {no}}"), "yes");
+        assert_eq!(render("{if 5 not_in '05' {yes} else {no}}"), "no");
+    }
+
+    #[test]
+    fn in_trims_spaced_lists() {
+        assert!(Template::list_contains(" a ,  b ", "b"));
+        assert!(!Template::list_contains("a b, c", "b"));
+        assert_eq!(render("{if 5 in '1, 5, 10' {yes} else {no}}"), "yes");
+        assert_eq!(render("{if 6 in '1, 5, 10' {yes} else {no}}"), "no");
+        assert_eq!(render("{if 6 not_in '1, 5, 10' {yes} else {no}}"), "yes");
+    }
+
+    #[test]
+    fn command_keeps_quoted_arguments_together() {
+        assert_eq!(render(r#"{cmd('printf [%s] --format="%h %s"')}"#), "[--format=%h %s]");
+        assert_eq!(render(r#"{cmd('printf [%s] a\ b "c \"d\""')}"#), r#"[a b][c "d"]"#);
+    }
+
+    #[test]
+    fn command_mixes_single_and_double_quotes() {
+        assert_eq!(render(r#"{cmd('printf [%s] "it's" b')}"#), "[it's][b]");
+        assert_eq!(render(r#"{cmd('printf [%s] 'say "hi"' x')}"#), r#"[say "hi"][x]"#);
+    }
+
+    #[test]
+    fn two_line_prompt_breaks_at_escaped_newline() {
+        let prompt = render("<s.b><s.cyan>~/code</s></s>\n{' '}on <s.magenta>main</s>\n\\n\n<s.green>❯</s>{' '}");
+        let (info, input) = prompt.split_once('\n').expect("prompt spans two lines");
+
+        assert_eq!(crate::tty::visible_width(info), "~/code on main".len());
+        assert_eq!(crate::tty::visible_width(input), 2);
+        assert!(!input.contains('\n'));
+    }
+
+    #[test]
+    fn range_forms_match_docs() {
+        assert_eq!(render("{for i in 1..=5 {{i}{' '}}}"), "1 2 3 4 5 ");
+        assert_eq!(render("{for n in 0..10..2 {{n}{' '}}}"), "0 2 4 6 8 ");
+        assert_eq!(render("{for n in 3..0 {{n}{' '}}}"), "3 2 1 ");
+        assert_eq!(render("{for x in 0..1..0.25 {{x}{' '}}}"), "0 0.25 0.5 0.75 ");
+    }
+
+    #[test]
+    fn range_length_is_capped() {
+        assert_eq!(Template::parse_range("0..10000").map(|numbers| numbers.len()), Some(10000));
+        assert_eq!(Template::parse_range("0..=10000"), None);
+        assert_eq!(Template::parse_range("1..1000000000"), None);
+        assert_eq!(Template::parse_range("0..1..0.00001"), None);
+    }
+
+    #[test]
+    fn match_flags_follow_the_group() {
+        assert_eq!(render("{'Feature/Login' | match('^feature', 0, 'i')}"), "Feature");
+        assert_eq!(render("{'Feature/Login' | match('feature/(\\w+)', 1, 'i')}"), "Login");
+        assert_eq!(render("{'v1 i' | match('v(?P<i>\\d+)', 'i')}"), "1");
+        assert_eq!(render("{'Feature' | match('^feature', 0, 'q')}"), "");
+    }
+}
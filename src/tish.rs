@@ -15,10 +15,12 @@ use anyhow::Result;
 use args::{Parser, TishArgs};
 use dashmap::DashSet;
 use jobs::JobManager;
-use shell::TishShell;
+use rustyline::{Cmd, KeyEvent};
+use shell::{completion::CompletionSpec, TishShell};
 
 use std::{
     collections::HashMap,
+    path::PathBuf,
     process::ExitCode,
     sync::{Arc, Mutex},
 };
@@ -29,6 +31,12 @@ lazy_lock! {
     pub static LUA_FN: Arc<DashSet<String>> = Arc::new(DashSet::new());
     pub static JOBS: Arc<Mutex<JobManager>> = Arc::new(Mutex::new(JobManager::new()));
     pub static ALIASES: Arc<Mutex<AliasMap>> = Arc::new(Mutex::new(AliasMap::new()));
+    pub static SUFFIX_ALIASES: Arc<Mutex<AliasMap>> = Arc::new(Mutex::new(AliasMap::new()));
+    pub static GLOBAL_ALIASES: Arc<Mutex<AliasMap>> = Arc::new(Mutex::new(AliasMap::new()));
+    pub static DIR_STACK: Arc<Mutex<Vec<PathBuf>>> = Arc::new(Mutex::new(Vec::new()));
+    pub static HISTORY_QUEUE: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+    pub static KEY_BINDINGS: Arc<Mutex<Vec<(KeyEvent, Cmd)>>> = Arc::new(Mutex::new(Vec::new()));
+    pub static COMPLETIONS: Arc<Mutex<HashMap<String, CompletionSpec>>> = Arc::new(Mutex::new(HashMap::new()));
 }
 
 pub mod prelude {
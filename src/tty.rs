@@ -6,6 +6,30 @@ extern "C" {
     fn isatty(fd: i32) -> i32;
 }
 
+pub fn stdout_is_tty() -> bool { unsafe { isatty(std::io::stdout().as_raw_fd()) == 1 } }
+
+pub fn stdin_is_tty() -> bool { unsafe { isatty(std::io::stdin().as_raw_fd()) == 1 } }
+
+// columns a string takes on screen, skipping csi/osc escape sequences like the ones prompt styles emit
+pub fn visible_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                Some('[') => while chars.next().is_some_and(|c| !('@'..='~').contains(&c)) {},
+                Some(']') => while chars.next().is_some_and(|c| c != '\x07' && c != '\x1b') {},
+                _ => {}
+            },
+            c if c.is_control() => {}
+            _ => width += 1,
+        }
+    }
+
+    width
+}
+
 pub fn get_tty_name() -> Option<String> {
     let fd = std::io::stdin().as_raw_fd();
     unsafe {